@@ -0,0 +1,121 @@
+//! Loads a directory hierarchy into a tree and prints it.
+//!
+//! ```text
+//! cargo run --example fs_tree [DIR] [DEPTH]
+//! ```
+//!
+//! The tree starts out as just `DIR` and is filled in by a single
+//! `dfs_nodes_mut` walk: each directory is listed when the walk enters it and
+//! its entries are grafted in as children, which the walk then goes on into.
+//! Only the directory being entered has its listing in memory, and the walk
+//! itself needs no stack. Directories with more entries than fit in a node
+//! have the rest summed up in an overflow node in the last slot.
+
+use std::{
+    env,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use constant_size_dfs::array_tree::{LendingIterator, Node, Tree};
+
+const FANOUT: usize = 8;
+
+enum DirEntryInfo {
+    /// A directory `depth` levels below the root, whose entries are loaded
+    /// once the walk gets to it.
+    Dir {
+        path: PathBuf,
+        depth: usize,
+    },
+    File {
+        name: String,
+        len: u64,
+    },
+    Overflow(usize),
+}
+
+impl Display for DirEntryInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dir { path, .. } => write!(f, "{}/", name(path)),
+            Self::File { name, len } => write!(f, "{name} ({len} bytes)"),
+            Self::Overflow(more) => write!(f, "... {more} more"),
+        }
+    }
+}
+
+/// The entries of `dir` sorted by name, so indices mean the same thing every
+/// time it's listed.
+fn entries(dir: &Path) -> Vec<fs::DirEntry> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(fs::DirEntry::file_name);
+    entries
+}
+
+fn is_overflow(index: usize, entries: &[fs::DirEntry]) -> bool {
+    index == FANOUT - 1 && entries.len() > FANOUT
+}
+
+fn name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn info(path: PathBuf, depth: usize) -> DirEntryInfo {
+    match fs::metadata(&path) {
+        Ok(meta) if meta.is_file() => DirEntryInfo::File {
+            name: name(&path),
+            len: meta.len(),
+        },
+        _ => DirEntryInfo::Dir { path, depth },
+    }
+}
+
+fn leaf(info: DirEntryInfo) -> Tree<DirEntryInfo, FANOUT> {
+    Tree::new(Some(Node::alloc(info, [const { None }; FANOUT])))
+}
+
+fn load(root: &Path, max_depth: usize) -> Tree<DirEntryInfo, FANOUT> {
+    let mut tree = leaf(info(root.to_owned(), 0));
+    let mut nodes = tree.dfs_nodes_mut();
+    while let Some(mut node) = nodes.next() {
+        let (entries, depth) = match node.value() {
+            DirEntryInfo::Dir { path, depth } if *depth < max_depth => (entries(path), depth + 1),
+            _ => continue,
+        };
+        for i in 0..FANOUT.min(entries.len()) {
+            let child = if is_overflow(i, &entries) {
+                DirEntryInfo::Overflow(entries.len() - (FANOUT - 1))
+            } else {
+                info(entries[i].path(), depth)
+            };
+            node.graft(i, leaf(child));
+        }
+    }
+    drop(nodes);
+    tree
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let root = PathBuf::from(args.next().unwrap_or_else(|| ".".to_owned()));
+    let depth = args.next().and_then(|d| d.parse().ok()).unwrap_or(2);
+
+    let tree = load(&root, depth);
+    print!("{tree}");
+    let bytes: u64 = tree
+        .dfs_iter()
+        .map(|info| match info {
+            DirEntryInfo::File { len, .. } => *len,
+            _ => 0,
+        })
+        .sum();
+    println!("{bytes} bytes in the files shown");
+}
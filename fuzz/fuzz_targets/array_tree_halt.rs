@@ -22,11 +22,13 @@ fuzz_target!(|data: &[u8]| {
     let Some((&size, data)) = data.split_first() else {
         return;
     };
-    // min size = 2, max size = 16 (inclusive)
-    const MIN_SIZE: usize = 2;
+    // min size = 0, max size = 8 (inclusive)
+    const MIN_SIZE: usize = 0;
     const MAX_SIZE: usize = 8;
     let size = (size as usize % (MAX_SIZE - MIN_SIZE + 1)) + MIN_SIZE;
     match size {
+        0 => fuzz::<0>(data),
+        1 => fuzz::<1>(data),
         2 => fuzz::<2>(data),
         3 => fuzz::<3>(data),
         4 => fuzz::<4>(data),
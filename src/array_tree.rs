@@ -1,302 +1,3874 @@
 use std::{
-    fmt::{self, Debug},
+    cell::Cell,
+    cmp::Ordering,
+    collections::HashSet,
+    error::Error,
+    fmt::{self, Debug, Display, Write as _},
+    io::{self, Read},
+    iter::FusedIterator,
     marker::PhantomData,
-    ptr::{self},
+    mem::ManuallyDrop,
+    ops::{Add, ControlFlow, Index, IndexMut},
+    ptr,
 };
 
-use crate::tagged_ptr::TaggedPtr;
+use crate::{observer::TraversalObserver, path::TreePath, tagged_ptr::TaggedPtr};
 
+/// Can be sent between threads, but isn't [`Sync`] since even iterating over a
+/// shared tree rewrites its links:
+///
+/// ```compile_fail
+/// use constant_size_dfs::array_tree::Tree;
+/// fn share(_: impl Sync) {}
+/// share(Tree::<u8, 2>::empty());
+/// ```
+///
+/// Values can borrow, but then the tree can't outlive what they borrow from:
+///
+/// ```compile_fail
+/// use constant_size_dfs::array_tree::{Node, Tree};
+/// let tree = {
+///     let word = String::from("root");
+///     Tree::<&str, 2>::new(Some(Node::alloc(&word[..], [None, None])))
+/// };
+/// drop(tree);
+/// ```
 pub struct Tree<T, const N: usize> {
-    root: *mut Node<T, N>,
+    /// Owns every node below it and mutates them. It's `*const` only so that
+    /// trees are covariant in `T` like `Box` is.
+    pub(crate) root: *const Node<T, N>,
 }
 
+// SAFETY: The tree owns its nodes and values, just like a `Box` would
+unsafe impl<T: Send, const N: usize> Send for Tree<T, N> {}
+
+impl<T, const N: usize> Default for Tree<T, N> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+/// Nests each node's children in brackets after its value, with `_` for empty
+/// slots. See [`Tree::debug_limited`] for big trees.
 impl<T: Debug, const N: usize> Debug for Tree<T, N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct(&format!("Tree<_, {N}>"))
-            .field("root", &TaggedPtr::from_untagged(self.root))
-            .finish()
+        self.debug_limited(usize::MAX, usize::MAX).fmt(f)
     }
 }
 
-#[derive(Debug)]
-pub struct Node<T, const N: usize> {
-    val: T,
-    children: [TaggedPtr<Node<T, N>>; N],
+/// Returned by [`Tree::debug_limited`].
+pub struct DebugLimited<'tree, T, const N: usize> {
+    tree: &'tree Tree<T, N>,
+    max_depth: usize,
+    max_nodes: usize,
 }
 
-impl<T, const N: usize> Tree<T, N> {
-    pub fn new(root: Option<Box<Node<T, N>>>) -> Self {
-        Self { root: to_ptr(root) }
-    }
-
-    pub fn dfs_iter_mut(&mut self) -> DfsIterMut<T, N> {
-        let iter = NodeIter {
-            prev: ptr::null_mut(),
-            cur: self.root,
-            lifetime: PhantomData,
-        };
-        DfsIterMut { iter }
+/// Panics if the tree is partway through a [`Tree::dfs_iter`].
+impl<T: Debug, const N: usize> Debug for DebugLimited<'_, T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        assert!(!self.tree.is_traversing(), "tree is being iterated over");
+        write!(f, "Tree<_, {N}> {{")?;
+        // How many brackets are open
+        let mut depth = 0;
+        let mut nodes = 0;
+        let mut iter = NodeIter::<T, N, 0>::new(self.tree.root);
+        while let Some((node, visited)) = iter.step() {
+            if visited == 0 {
+                if depth == 0 {
+                    f.write_char(' ')?;
+                }
+                if nodes == self.max_nodes {
+                    f.write_str("..")?;
+                    break;
+                }
+                nodes += 1;
+                // SAFETY: We've only just stepped into this node, and its first
+                // slot is the only one that's been moved
+                let node_ref = unsafe { &*node };
+                node_ref.val.fmt(f)?;
+                let is_leaf = iter.first_child(node).is_null()
+                    && node_ref
+                        .children
+                        .iter()
+                        .skip(1)
+                        .all(|c| c.as_untagged().is_null());
+                if is_leaf || depth == self.max_depth {
+                    if !is_leaf {
+                        f.write_str(" [..]")?;
+                    }
+                    iter.skip_children(node);
+                    continue;
+                }
+                f.write_str(" [")?;
+                depth += 1;
+            } else if visited == N {
+                f.write_char(']')?;
+                depth -= 1;
+                continue;
+            } else {
+                f.write_str(", ")?;
+            }
+            // We only stay put when the slot we just came to is empty
+            if ptr::eq(iter.cur, node) {
+                f.write_char('_')?;
+            }
+        }
+        for _ in 0..depth {
+            f.write_char(']')?;
+        }
+        f.write_str(" }")
     }
 }
 
-type Fence = u16;
+/// Returned by [`Tree::debug_dump`].
+pub struct DebugDump<'tree, T, const N: usize> {
+    root: *const Node<T, N>,
+    lifetime: PhantomData<&'tree ()>,
+}
 
-impl<const N: usize> Tree<u8, N> {
-    pub fn arbitrary(data: &[u8]) -> (Self, Vec<u8>) {
-        assert!(data.len() < Fence::MAX as usize, "Fence size is too small");
-        let mut values = Vec::with_capacity(data.len());
-        let root = node_from_arbitrary(data, &mut values);
-        let tree = Self::new(root);
-        (tree, values)
+impl<T: Debug, const N: usize> Debug for DebugDump<'_, T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // This doesn't trust the tree's shape, so it keeps its own stack and
+        // remembers where it's been rather than walking the links
+        let mut printed = HashSet::new();
+        let mut slots = vec![(TaggedPtr::from_untagged(self.root.cast_mut()), 0)];
+        while let Some((slot, depth)) = slots.pop() {
+            write!(f, "{:indent$}", "", indent = 2 * depth)?;
+            let node = slot.as_untagged();
+            if slot.is_seen() || node.is_null() {
+                writeln!(f, "{slot:?}")?;
+                continue;
+            }
+            if !printed.insert(node) {
+                writeln!(f, "{slot:?} again")?;
+                continue;
+            }
+            // SAFETY: Unseen links always point down to live nodes
+            let node = unsafe { &*node };
+            writeln!(f, "{slot:?} {:?}", node.val)?;
+            slots.extend(node.children.iter().rev().map(|&child| (child, depth + 1)));
+        }
+        Ok(())
     }
 }
 
-fn node_from_arbitrary<const N: usize>(
-    data: &[u8],
-    values: &mut Vec<u8>,
-) -> Option<Box<Node<u8, N>>> {
-    let (&val, data) = data.split_first()?;
-    values.push(val);
+/// Looks up the value at a path of child indices.
+///
+/// Panics if there's no node there. See [`Tree::get`] for a non-panicking
+/// version.
+impl<T, P: AsRef<[usize]>, const N: usize> Index<P> for Tree<T, N> {
+    type Output = T;
 
-    let mut children = [const { None }; N];
-    let num_mid_fences = N - 1;
-    let Some((mid_fences, data)) = data.split_at_checked(num_mid_fences * size_of::<Fence>())
-    else {
-        return Some(Node::alloc(val, children));
-    };
+    fn index(&self, path: P) -> &T {
+        let path = path.as_ref();
+        self.get(path)
+            .unwrap_or_else(|| panic!("no node at path {path:?}"))
+    }
+}
 
-    let mut fences = [0; N];
-    *fences.last_mut().unwrap() = data.len();
-    for (i, v) in mid_fences.chunks(size_of::<Fence>()).enumerate() {
-        let v = Fence::from_ne_bytes(v.try_into().unwrap());
-        fences[i + 1] = v as usize % (data.len() + 1);
+impl<T, P: AsRef<[usize]>, const N: usize> IndexMut<P> for Tree<T, N> {
+    fn index_mut(&mut self, path: P) -> &mut T {
+        let path = path.as_ref();
+        self.get_mut(path)
+            .unwrap_or_else(|| panic!("no node at path {path:?}"))
     }
-    fences.sort();
+}
 
-    for (i, slot) in children.iter_mut().enumerate() {
-        let range = fences[i]..fences.get(i + 1).copied().unwrap_or(data.len());
-        *slot = node_from_arbitrary(&data[range], values);
+/// Lists the values in preorder, one per line, indented two spaces per level.
+///
+/// Panics if the tree is partway through a [`Tree::dfs_iter`].
+impl<T: Display, const N: usize> Display for Tree<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        assert!(!self.is_traversing(), "tree is being iterated over");
+        let mut depth = 0;
+        let mut iter = NodeIter::<T, N, 0>::new(self.root);
+        while let Some((node, visited)) = iter.step() {
+            if visited == 0 {
+                // SAFETY: Values aren't touched by the traversal
+                writeln!(
+                    f,
+                    "{:indent$}{}",
+                    "",
+                    unsafe { &(*node).val },
+                    indent = 2 * depth
+                )?;
+                depth += 1;
+            }
+            if visited == N {
+                depth -= 1;
+            }
+        }
+        Ok(())
     }
-    Some(Node::alloc(val, children))
 }
 
-fn to_ptr<T>(node: Option<Box<T>>) -> *mut T {
-    node.map(|n| Box::leak(n) as *mut _)
-        .unwrap_or(ptr::null_mut())
+#[derive(Debug)]
+pub struct Node<T, const N: usize> {
+    pub(crate) val: T,
+    pub(crate) children: [TaggedPtr<Node<T, N>>; N],
 }
 
-impl<T, const N: usize> Node<T, N> {
-    pub fn alloc(val: T, children: [Option<Box<Node<T, N>>>; N]) -> Box<Node<T, N>> {
-        let mut converted = [TaggedPtr::from_untagged(ptr::null_mut()); N];
-        for (slot, node) in converted.iter_mut().zip(children) {
-            *slot = TaggedPtr::from_untagged(to_ptr(node));
-        }
-        Box::new(Node {
-            val,
-            children: converted,
-        })
-    }
+/// Why [`Tree::try_from_parts`] rejected its input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StructureError {
+    /// `node` pointed at a `child` that wasn't given.
+    OutOfBounds { node: usize, child: usize },
+    /// `node` is the root or another node's child too, so following the links
+    /// would get to it twice.
+    ReachedTwice { node: usize },
+    /// `node` can't be reached from the root.
+    Unreachable { node: usize },
 }
 
-impl<T, const N: usize> Drop for Tree<T, N> {
-    fn drop(&mut self) {
-        // We want to visit the leaves first
-        let iter = NodeIter::<T, N, N> {
-            prev: ptr::null_mut(),
-            cur: self.root,
-            lifetime: PhantomData,
-        };
-        for node in iter {
-            let _ = unsafe { Box::from_raw(node) };
+impl Display for StructureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfBounds { node, child } => {
+                write!(f, "node {node} has child {child}, which doesn't exist")
+            }
+            Self::ReachedTwice { node } => write!(f, "node {node} has more than one parent"),
+            Self::Unreachable { node } => write!(f, "node {node} isn't connected to the root"),
         }
     }
 }
 
-pub struct NodeIter<'tree, T, const N: usize, const RETURN_ON_VISIT: usize> {
-    prev: *mut Node<T, N>,
-    cur: *mut Node<T, N>,
-    lifetime: PhantomData<&'tree T>,
-}
+impl Error for StructureError {}
 
-// NOTE: It's okay if this doesn't run. The tree will leak some nodes but be
-// safe
-impl<'tree, T, const N: usize, const RETURN_ON_VISIT: usize> Drop
-    for NodeIter<'tree, T, N, RETURN_ON_VISIT>
-{
-    fn drop(&mut self) {
-        // Ascend the tree until we reach the top (i.e. null self.cur) and
-        while let Some(cur) = unsafe { self.cur.as_mut() } {
+impl<T, const N: usize> Tree<T, N> {
+    pub fn new(root: Option<Box<Node<T, N>>>) -> Self {
+        Self { root: to_ptr(root) }
+    }
+
+    /// Builds a tree by asking `f` for the value at each position, given as the
+    /// child indices leading to it from the root. Nothing is asked below a
+    /// `None` or more than `depth` levels below the root.
+    ///
+    /// Positions are asked about in preorder, and the tree is grown in step
+    /// with a traversal of itself, so nothing but the path is kept on the side.
+    pub fn from_fn(depth: usize, mut f: impl FnMut(&[usize]) -> Option<T>) -> Self {
+        let mut path = Vec::with_capacity(depth);
+        let Some(root) = f(&path) else {
+            return Self::empty();
+        };
+        let tree = Self::new(Some(Node::alloc(root, [const { None }; N])));
+        // Declared after `tree` so it's put back together before being freed if
+        // `f` panics
+        let mut iter = NodeIter::<T, N, 0>::new(tree.root);
+        while let Some(cur) = unsafe { iter.cur.as_mut() } {
             let first_unvisited = cur
                 .children
                 .iter()
                 .position(|node_ptr| !node_ptr.is_seen())
                 .unwrap_or(N);
-
-            if first_unvisited == 0 {
-                // If we haven't visited any children, then our previous node is our parent
-                self.cur = self.prev;
-                self.prev = cur;
-            } else {
-                let parent = cur.children[0];
-                for i in 0..(first_unvisited - 1) {
-                    cur.children[i] = cur.children[i + 1].unseen();
+            if first_unvisited == N {
+                path.pop();
+            } else if path.len() < depth {
+                // Fill in the child we're about to step into
+                path.push(first_unvisited);
+                match f(&path) {
+                    Some(val) => {
+                        let child = Node::alloc(val, [const { None }; N]);
+                        cur.children[first_unvisited] =
+                            TaggedPtr::from_untagged(Box::into_raw(child));
+                    }
+                    None => {
+                        path.pop();
+                    }
                 }
-                cur.children[first_unvisited - 1] = TaggedPtr::from_untagged(self.prev).unseen();
-                self.cur = parent.as_untagged();
-                self.prev = cur;
             }
+            iter.step();
         }
+        drop(iter);
+        tree
     }
-}
-
-impl<'tree, T, const N: usize, const RETURN_ON_VISIT: usize> Iterator
-    for NodeIter<'tree, T, N, RETURN_ON_VISIT>
-{
-    type Item = *mut Node<T, N>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            // SAFETY: We're guarnteed the pointers live for the lifespan of 'tree
-            let cur: &'tree mut Node<T, N> = unsafe { self.cur.as_mut()? };
 
+    /// Writes the tree out in the form [`Tree::decode_from`] reads, using
+    /// `encode` for each value. Values come out in preorder, each preceded by
+    /// a byte saying whether its slot is filled, and empty slots are just that
+    /// byte.
+    ///
+    /// Panics if the tree is partway through a [`Tree::dfs_iter`].
+    pub fn encode_to<W: io::Write>(
+        &self,
+        writer: &mut W,
+        mut encode: impl FnMut(&T, &mut W) -> io::Result<()>,
+    ) -> io::Result<()> {
+        assert!(!self.is_traversing(), "tree is being iterated over");
+        // SAFETY: No traversal is in progress so the root is in place
+        let Some(root) = (unsafe { self.root.as_ref() }) else {
+            return writer.write_all(&[0]);
+        };
+        writer.write_all(&[1])?;
+        encode(&root.val, writer)?;
+        // Stops wherever the writer fails, leaving `iter` to put the tree back
+        let mut iter = NodeIter::<T, N, 0>::new(self.root);
+        while let Some(cur) = unsafe { iter.cur.as_ref() } {
             let first_unvisited = cur
                 .children
                 .iter()
                 .position(|node_ptr| !node_ptr.is_seen())
                 .unwrap_or(N);
             if first_unvisited < N {
-                // Visit that child
-                let child_to_visit = cur.children[first_unvisited].as_untagged();
-                cur.children[first_unvisited] = TaggedPtr::from_untagged(self.prev).seen();
-                if child_to_visit.is_null() {
-                    // Return like we just visited this node
-                    self.prev = child_to_visit;
-                } else {
-                    self.cur = child_to_visit;
-                    self.prev = cur;
+                let child = unsafe { cur.children[first_unvisited].as_untagged().as_ref() };
+                writer.write_all(&[child.is_some() as u8])?;
+                if let Some(child) = child {
+                    encode(&child.val, writer)?;
                 }
-            } else {
-                // Visited all children, go re-construct things and go up.
-                if first_unvisited == 0 {
-                    // If we haven't visited any children, then our previous node is our parent
-                    self.cur = self.prev;
-                    self.prev = cur;
-                } else {
-                    let parent = cur.children[0];
-                    for i in 0..(first_unvisited - 1) {
-                        cur.children[i] = cur.children[i + 1].unseen();
-                    }
-                    cur.children[first_unvisited - 1] =
-                        TaggedPtr::from_untagged(self.prev).unseen();
-                    self.cur = parent.as_untagged();
-                    self.prev = cur;
+            }
+            iter.step();
+        }
+        Ok(())
+    }
+
+    /// Reads a tree written by [`Tree::encode_to`], using `decode` for each
+    /// value. Nodes are added as they're read so the input is never held in
+    /// memory, and like [`Tree::from_fn`] nothing else is kept on the side.
+    pub fn decode_from<R: Read>(
+        reader: &mut R,
+        mut decode: impl FnMut(&mut R) -> io::Result<T>,
+    ) -> io::Result<Self> {
+        if !read_filled(reader)? {
+            return Ok(Self::empty());
+        }
+        let tree = Self::new(Some(Node::alloc(decode(reader)?, [const { None }; N])));
+        // Declared after `tree` so it's put back together before being freed if
+        // reading fails
+        let mut iter = NodeIter::<T, N, 0>::new(tree.root);
+        while let Some(cur) = unsafe { iter.cur.as_mut() } {
+            let first_unvisited = cur
+                .children
+                .iter()
+                .position(|node_ptr| !node_ptr.is_seen())
+                .unwrap_or(N);
+            if first_unvisited < N && read_filled(reader)? {
+                let child = Node::alloc(decode(reader)?, [const { None }; N]);
+                cur.children[first_unvisited] = TaggedPtr::from_untagged(Box::into_raw(child));
+            }
+            iter.step();
+        }
+        drop(iter);
+        Ok(tree)
+    }
+
+    /// Builds a tree out of numbered nodes, each given as its value and the
+    /// numbers of its children. Node 0 is the root, and no nodes at all makes
+    /// an empty tree.
+    ///
+    /// The input is checked to really be a tree before anything is built, since
+    /// a node shared between parents or a cycle would send traversals astray.
+    pub fn try_from_parts(parts: Vec<(T, [Option<usize>; N])>) -> Result<Self, StructureError> {
+        let len = parts.len();
+        let mut reached = vec![false; len];
+        let mut unexplored = Vec::new();
+        if len > 0 {
+            reached[0] = true;
+            unexplored.push(0);
+        }
+        while let Some(node) = unexplored.pop() {
+            for &child in parts[node].1.iter().flatten() {
+                match reached.get_mut(child) {
+                    None => return Err(StructureError::OutOfBounds { node, child }),
+                    Some(true) => return Err(StructureError::ReachedTwice { node: child }),
+                    Some(reached) => *reached = true,
                 }
+                unexplored.push(child);
             }
+        }
+        if let Some(node) = reached.iter().position(|&reached| !reached) {
+            return Err(StructureError::Unreachable { node });
+        }
 
-            if first_unvisited == RETURN_ON_VISIT {
-                return Some(cur);
+        // Every node now has exactly one owner, so linking them up can't fail
+        let (values, children): (Vec<_>, Vec<_>) = parts.into_iter().unzip();
+        let nodes: Vec<_> = values
+            .into_iter()
+            .map(|val| Box::into_raw(Node::alloc(val, [const { None }; N])))
+            .collect();
+        for (&node, children) in nodes.iter().zip(children) {
+            for (slot, child) in children.into_iter().enumerate() {
+                if let Some(child) = child {
+                    // SAFETY: Each node is only ever linked to here
+                    unsafe { (*node).children[slot] = TaggedPtr::from_untagged(nodes[child]) };
+                }
             }
         }
+        Ok(Self::from_raw(
+            nodes.first().copied().unwrap_or(ptr::null_mut()),
+        ))
     }
-}
 
-pub struct DfsIterMut<'tree, T, const N: usize> {
-    iter: NodeIter<'tree, T, N, 0>,
-}
+    /// A tree with no nodes at all.
+    pub const fn empty() -> Self {
+        Self {
+            root: ptr::null_mut(),
+        }
+    }
 
-impl<'tree, T, const N: usize> Iterator for DfsIterMut<'tree, T, N> {
-    type Item = &'tree mut T;
+    pub fn is_empty(&self) -> bool {
+        self.root.is_null()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iter
-            .next()
-            .map(|node| unsafe { &mut node.as_mut().expect("should not be null").val })
+    pub fn root(&self) -> Option<&T> {
+        // SAFETY: Traversals never move the root or touch values
+        (!self.root.is_null()).then(|| unsafe { &(*self.root).val })
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    pub fn root_mut(&mut self) -> Option<&mut T> {
+        (!self.root.is_null()).then(|| unsafe { &mut (*self.root.cast_mut()).val })
+    }
 
-    fn assert_dfs_valid<T: Clone + Debug + PartialEq, const N: usize>(
-        expected: impl IntoIterator<Item = T>,
-        mut tree: Tree<T, N>,
-    ) {
-        let expected: Vec<T> = expected.into_iter().collect();
-        let actual: Vec<T> = tree.dfs_iter_mut().map(|v| v.clone()).collect();
-        assert_eq!(expected, actual);
+    /// Follows child indices down from the root, returning null if they lead
+    /// nowhere.
+    pub(crate) fn find(&self, path: &[usize]) -> *mut Node<T, N> {
+        assert!(!self.is_traversing(), "tree is being iterated over");
+        let mut node = self.root.cast_mut();
+        for &i in path {
+            // SAFETY: No traversal is in progress so the links are all in place
+            let Some(child) = unsafe { node.as_ref() }.and_then(|n| n.children.get(i)) else {
+                return ptr::null_mut();
+            };
+            node = child.as_untagged();
+        }
+        node
     }
 
-    fn null<T, const N: usize>() -> TaggedPtr<Node<T, N>> {
-        TaggedPtr::from_untagged(ptr::null_mut())
+    /// The node reached by following child indices down from the root. See
+    /// [`TreePath`](crate::path::TreePath).
+    pub fn node_at(&self, path: impl AsRef<[usize]>) -> Option<NodeRef<'_, T, N>> {
+        let node = self.find(path.as_ref());
+        (!node.is_null()).then_some(NodeRef { tree: self, node })
     }
 
-    fn node<T, const N: usize>(
-        val: T,
-        children: [TaggedPtr<Node<T, N>>; N],
-    ) -> TaggedPtr<Node<T, N>> {
-        let node = Node { val, children };
-        TaggedPtr::from_untagged(Box::into_raw(Box::new(node)))
+    /// The value at `path`, or `None` if there's no node there.
+    pub fn get(&self, path: impl AsRef<[usize]>) -> Option<&T> {
+        // SAFETY: `find` only returns live nodes
+        unsafe { self.find(path.as_ref()).as_ref() }.map(|n| &n.val)
     }
 
-    fn leaf<T, const N: usize>(val: T) -> TaggedPtr<Node<T, N>> {
-        node(val, [null(); N])
+    pub fn get_mut(&mut self, path: impl AsRef<[usize]>) -> Option<&mut T> {
+        unsafe { self.find(path.as_ref()).as_mut() }.map(|n| &mut n.val)
     }
 
-    fn tree<T, const N: usize>(root: TaggedPtr<Node<T, N>>) -> Tree<T, N> {
-        Tree {
-            root: root.as_untagged(),
+    /// The value of child `i` of the node at `path`, first creating it as a
+    /// leaf holding `default` if the slot is empty.
+    ///
+    /// Panics if there's no node at `path` or `i` isn't less than `N`.
+    pub fn get_or_insert_child(
+        &mut self,
+        path: impl AsRef<[usize]>,
+        i: usize,
+        default: T,
+    ) -> &mut T {
+        let path = path.as_ref();
+        // SAFETY: `find` only returns live nodes
+        let Some(node) = (unsafe { self.find(path).as_mut() }) else {
+            panic!("no node at path {path:?}");
+        };
+        let slot = &mut node.children[i];
+        if slot.as_untagged().is_null() {
+            let child = Node::alloc(default, [const { None }; N]);
+            *slot = slot.retarget(Box::into_raw(child));
         }
+        unsafe { &mut (*slot.as_untagged()).val }
     }
 
-    #[test]
-    fn empty() {
-        assert_dfs_valid::<i32, 2>([], tree(null()));
+    /// Puts `subtree` into the first empty child slot of the node at `path`,
+    /// or hands it back if every slot is taken.
+    ///
+    /// Panics if there's no node at `path`.
+    pub fn append_child(
+        &mut self,
+        path: impl AsRef<[usize]>,
+        subtree: Tree<T, N>,
+    ) -> Result<(), Tree<T, N>> {
+        let path = path.as_ref();
+        // SAFETY: `find` only returns live nodes
+        let Some(node) = (unsafe { self.find(path).as_mut() }) else {
+            panic!("no node at path {path:?}");
+        };
+        if subtree.is_empty() {
+            return Ok(());
+        }
+        match node.children.iter_mut().find(|c| c.as_untagged().is_null()) {
+            Some(slot) => {
+                *slot = slot.retarget(subtree.into_raw());
+                Ok(())
+            }
+            None => Err(subtree),
+        }
     }
 
-    #[test]
-    fn one() {
-        assert_dfs_valid::<_, 2>([0], tree(leaf(0)));
+    /// Cuts off the subtree at `path`, leaving its slot empty but still tagged.
+    /// Returns an empty tree if there's nothing there.
+    pub fn detach(&mut self, path: impl AsRef<[usize]>) -> Tree<T, N> {
+        let Some((&i, parent)) = path.as_ref().split_last() else {
+            return std::mem::take(self);
+        };
+        // SAFETY: `find` only returns live nodes
+        let Some(slot) = unsafe { self.find(parent).as_mut() }.and_then(|n| n.children.get_mut(i))
+        else {
+            return Tree::empty();
+        };
+        let child = slot.as_untagged();
+        *slot = slot.retarget(ptr::null_mut());
+        Tree::from_raw(child)
     }
 
-    #[test]
-    fn two() {
-        assert_dfs_valid([0, 1], tree(node(0, [leaf(1), null()])));
+    /// Folds `f` over the values in preorder, straight off the traversal
+    /// rather than through an iterator.
+    ///
+    /// Panics if the tree is partway through a [`Tree::dfs_iter`].
+    fn fold_values<'a, B>(&'a self, init: B, mut f: impl FnMut(B, &'a T) -> B) -> B {
+        assert!(!self.is_traversing(), "tree is already being iterated over");
+        let mut acc = init;
+        // If `f` panics the traversal puts the links back as it's dropped
+        let mut iter = NodeIter::<T, N, 0>::new(self.root);
+        while let Some((node, visited)) = iter.step() {
+            if visited == 0 {
+                // SAFETY: Traversals never touch values
+                acc = f(acc, unsafe { &(*node).val });
+            }
+        }
+        acc
     }
 
-    #[test]
-    fn basic() {
-        assert_dfs_valid(
-            0..=5,
-            tree(node(
-                0,
-                [node(1, [leaf(2), null()]), node(3, [leaf(4), leaf(5)])],
-            )),
-        );
+    /// Adds up the values, starting from `S::default()`.
+    pub fn sum<'a, S: Default + Add<&'a T, Output = S>>(&'a self) -> S {
+        self.fold_values(S::default(), |sum, val| sum + val)
     }
 
-    #[test]
-    fn nochildren() {
-        assert_dfs_valid(["hi"], tree(node("hi", [])));
+    /// The smallest value, the first of them if there's a tie.
+    pub fn min(&self) -> Option<&T>
+    where
+        T: Ord,
+    {
+        self.fold_values(None, |min, val| match min {
+            Some(min) if min <= val => Some(min),
+            _ => Some(val),
+        })
     }
 
-    #[test]
-    fn linked_list() {
-        let list = node(0, [node(1, [node(2, [leaf(3)])])]);
-        assert_dfs_valid(0..=3, tree(list));
+    /// The largest value, the last of them if there's a tie.
+    pub fn max(&self) -> Option<&T>
+    where
+        T: Ord,
+    {
+        self.fold_values(None, |max, val| match max {
+            Some(max) if max > val => Some(max),
+            _ => Some(val),
+        })
     }
 
-    #[test]
-    fn iter_fixes_tree() {
-        let mut tree = tree(node(
-            0,
-            [node(1, [leaf(2), null()]), node(3, [leaf(4), leaf(5)])],
-        ));
-        let mut iter = tree.dfs_iter_mut();
-        assert_eq!(Some(&mut 0), iter.next());
-        assert_eq!(Some(&mut 1), iter.next());
-        assert_eq!(Some(&mut 2), iter.next());
-        drop(iter);
-        assert_dfs_valid(0..=5, tree);
+    pub fn count_if(&self, mut predicate: impl FnMut(&T) -> bool) -> usize {
+        self.fold_values(0, |count, val| count + predicate(val) as usize)
+    }
+
+    /// Hands each value its position in preorder, starting from 0 at the root,
+    /// so that side tables can be indexed by it. Returns how many nodes there
+    /// are.
+    pub fn number_preorder(&mut self, mut f: impl FnMut(&mut T, usize)) -> usize {
+        let mut count = 0;
+        for val in self.dfs_iter_mut() {
+            f(val, count);
+            count += 1;
+        }
+        count
+    }
+
+    /// Reorders every node's children by `key` of their values, with empty
+    /// slots moved to the end. The sort is stable and doesn't allocate, but
+    /// `key` isn't cached, so it's called O(N log N) times per node. Slot tags
+    /// stay where they are rather than following the children.
+    pub fn sort_children_by_key<K: Ord>(&mut self, mut key: impl FnMut(&T) -> K) {
+        // Post-order, so each node's children are back in place when we get
+        // to it
+        for node in NodeIter::<T, N, N>::new(self.root) {
+            // SAFETY: The node is finished and nothing else is borrowing it
+            let children = unsafe { &mut (*node).children };
+            // Sorted on the side so a panicking `key` leaves the node alone,
+            // with the slot index breaking ties to keep it stable
+            let mut sorted: [_; N] = std::array::from_fn(|i| (children[i], i));
+            sorted.sort_unstable_by_key(|&(child, i)| {
+                // SAFETY: Values are never touched by the traversal
+                let key = unsafe { child.as_untagged().as_ref() }.map(|c| key(&c.val));
+                (key.ok_or(()), i)
+            });
+            for (slot, (child, _)) in children.iter_mut().zip(sorted) {
+                *slot = slot.retarget(child.as_untagged());
+            }
+        }
+    }
+
+    /// Whether `other` fits over the top of this tree, with every node of it
+    /// in the same place here and holding an equal value. Stops at the first
+    /// node that doesn't match.
+    ///
+    /// Panics if either tree is partway through a [`Tree::dfs_iter`].
+    pub fn contains_shape_of(&self, other: &Self) -> bool
+    where
+        T: PartialEq,
+    {
+        assert!(!self.is_traversing(), "tree is being iterated over");
+        assert!(!other.is_traversing(), "tree is being iterated over");
+        if ptr::eq(self, other) {
+            // Walking the same nodes twice at once would tangle their links
+            return true;
+        }
+        if other.root.is_null() {
+            return true;
+        }
+        if self.root.is_null() {
+            return false;
+        }
+        // Both walks take the same slots at the same time, so they're always
+        // at corresponding nodes. Ours passes over whatever `other` doesn't
+        // have.
+        let mut iter = NodeIter::<T, N, 0>::new(self.root);
+        let mut other_iter = NodeIter::<T, N, 0>::new(other.root);
+        // SAFETY: Current nodes are live, and values and the slots ahead of
+        // the traversal are left alone
+        while let Some(theirs) = unsafe { other_iter.cur.as_ref() } {
+            let ours = unsafe { &*iter.cur };
+            let first_unvisited = theirs
+                .children
+                .iter()
+                .position(|node_ptr| !node_ptr.is_seen())
+                .unwrap_or(N);
+            if first_unvisited == 0 && ours.val != theirs.val {
+                return false;
+            }
+            if first_unvisited < N {
+                let their_child = theirs.children[first_unvisited].as_untagged();
+                let our_child = ours.children[first_unvisited].as_untagged();
+                if their_child.is_null() {
+                    iter.step_over();
+                    other_iter.step();
+                    continue;
+                }
+                if our_child.is_null() {
+                    return false;
+                }
+            }
+            iter.step();
+            other_iter.step();
+        }
+        true
+    }
+
+    /// Searches in preorder for the first value matching `predicate`, leaving
+    /// its position in `path`. `path` is kept up to date as the search goes
+    /// rather than worked out at the end, and is left empty if nothing matches.
+    pub fn find_with_path(
+        &mut self,
+        path: &mut TreePath,
+        mut predicate: impl FnMut(&T) -> bool,
+    ) -> Option<&mut T> {
+        path.clear();
+        let mut iter = NodeIter::<T, N, 0>::new(self.root);
+        while let Some((node, visited)) = iter.step() {
+            if visited == 0 {
+                // SAFETY: We've only just stepped into this node
+                if let Some((_, slot)) = unsafe { ancestors(node) }.next() {
+                    path.push(slot);
+                }
+                // Values are never touched by the traversal, so this outlives it
+                let val = unsafe { &mut (*node).val };
+                if predicate(val) {
+                    return Some(val);
+                }
+            }
+            if visited == N {
+                path.pop();
+            }
+        }
+        None
+    }
+
+    /// Swaps in the whole of `new`, handing back the old tree as it was.
+    /// Nothing is dropped, so the old tree can be freed whenever suits.
+    pub fn replace_root(&mut self, new: Tree<T, N>) -> Tree<T, N> {
+        std::mem::replace(self, new)
+    }
+
+    /// Splits the tree in two, returning what's left and the subtree that was
+    /// at `path`.
+    pub fn split_off_at_path(mut self, path: impl AsRef<[usize]>) -> (Tree<T, N>, Tree<T, N>) {
+        let subtree = self.detach(path);
+        (self, subtree)
+    }
+
+    /// How many children the node at `path` has, not counting empty slots.
+    pub fn arity_at(&self, path: impl AsRef<[usize]>) -> Option<usize> {
+        self.node_at(path).map(|node| node.arity())
+    }
+
+    /// Whether there's a node at `path` with at least one child.
+    pub fn has_children(&self, path: impl AsRef<[usize]>) -> bool {
+        self.arity_at(path).is_some_and(|arity| arity > 0)
+    }
+
+    /// A handle on the root for poking around the tree without a traversal.
+    pub fn root_node(&self) -> Option<NodeRef<'_, T, N>> {
+        (!self.root.is_null()).then_some(NodeRef {
+            tree: self,
+            node: self.root,
+        })
+    }
+
+    /// Iterates over the values in preorder.
+    ///
+    /// The traversal still rewires the tree as it goes, so only one shared
+    /// iteration can be in progress at a time. Panics if another one is
+    /// partway through.
+    pub fn dfs_iter(&self) -> DfsIter<'_, T, N> {
+        DfsIter {
+            iter: NodeIter::new(self.root),
+            tree: self,
+            started: false,
+        }
+    }
+
+    pub fn dfs_iter_mut(&mut self) -> DfsIterMut<'_, T, N> {
+        self.dfs_iter_mut_with(())
+    }
+
+    /// Like [`Self::dfs_iter_mut`] but starting `position` values in, e.g. to
+    /// carry on from a [`checkpoint`](crate::checkpoint). The values before
+    /// that are stepped past without being touched.
+    pub fn dfs_iter_mut_from(&mut self, position: usize) -> DfsIterMut<'_, T, N> {
+        let mut iter = self.dfs_iter_mut();
+        iter.iter.by_ref().take(position).for_each(drop);
+        iter
+    }
+
+    /// Goes through every step of a preorder traversal, for building other
+    /// traversals on. Each node comes up once for each slot and once more when
+    /// it's finished.
+    pub fn raw_walk(&mut self) -> RawWalk<'_, T, N> {
+        RawWalk {
+            iter: NodeIter::new(self.root),
+        }
+    }
+
+    /// Like [`Self::dfs_iter_mut`] but goes into each node's children in the
+    /// order `compare` puts their values in, with ties kept in slot order.
+    ///
+    /// The slots are left where they are. Instead, every time the traversal is
+    /// back at a node it scans the unvisited children for the best one, so a
+    /// node with `k` children costs `O(k * N)` comparisons.
+    pub fn dfs_iter_mut_best_first<F: FnMut(&T, &T) -> Ordering>(
+        &mut self,
+        compare: F,
+    ) -> DfsBestFirstMut<'_, T, N, F> {
+        DfsBestFirstMut {
+            prev: ptr::null_mut(),
+            cur: self.root.cast_mut(),
+            compare,
+            lifetime: PhantomData,
+        }
+    }
+
+    /// Like [`Self::dfs_iter_mut`] but visits each node's children in a random
+    /// order, which only depends on `seed` and where the node is in the tree,
+    /// so clones come out the same way too. Child slots are shuffled on the way
+    /// in and put back on the way out.
+    pub fn dfs_iter_mut_shuffled(&mut self, seed: u64) -> DfsReorderedMut<'_, T, N> {
+        DfsReorderedMut::new(
+            self.root,
+            ChildOrder::Shuffled {
+                seed,
+                path: 0,
+                depth: 0,
+            },
+        )
+    }
+
+    /// Like [`Self::dfs_iter_mut`] but visits each node's children from the
+    /// last slot to the first. Child slots are reversed on the way in and put
+    /// back on the way out.
+    pub fn dfs_iter_mut_rev_children(&mut self) -> DfsReorderedMut<'_, T, N> {
+        DfsReorderedMut::new(self.root, ChildOrder::Reversed)
+    }
+
+    /// Like [`Self::dfs_iter_mut`] but says how many children each value's
+    /// node has too.
+    pub fn dfs_visits_mut(&mut self) -> DfsVisitsMut<'_, T, N> {
+        DfsVisitsMut {
+            iter: NodeIter::new(self.root),
+        }
+    }
+
+    /// Like [`Self::dfs_iter_mut`] but with a look at each value's parent, for
+    /// passing things down the tree.
+    pub fn dfs_with_parent_mut(&mut self) -> DfsWithParentMut<'_, T, N> {
+        DfsWithParentMut {
+            iter: NodeIter::new(self.root),
+        }
+    }
+
+    /// Walks the tree once, reporting when each node is entered, when each of
+    /// its slots is done and when it's left.
+    pub fn walk_mut(&mut self) -> WalkMut<'_, T, N> {
+        WalkMut {
+            iter: NodeIter::new(self.root),
+            exit: ptr::null_mut(),
+        }
+    }
+
+    /// Like [`Self::dfs_iter_mut`] but never goes deeper than `max_depth`,
+    /// counting the root as 0. Nothing below that is touched.
+    pub fn dfs_iter_mut_bounded(&mut self, max_depth: usize) -> DfsBoundedMut<'_, T, N> {
+        DfsBoundedMut::new(self.root, max_depth)
+    }
+
+    /// Finds the shallowest value matching `predicate`, no deeper than
+    /// `max_depth`, along with its depth. Ties go to the earlier value in
+    /// preorder.
+    ///
+    /// Searches with iterative deepening, so it only keeps to constant space.
+    /// Each level costs a traversal of everything above it. `predicate` is
+    /// called once for each value it gets to.
+    pub fn ids_find(
+        &mut self,
+        mut predicate: impl FnMut(&T) -> bool,
+        max_depth: usize,
+    ) -> Option<(&mut T, usize)> {
+        for limit in 0..=max_depth {
+            let mut pass = DfsBoundedMut::new(self.root, limit);
+            let mut reached = false;
+            while let Some(node) = pass.enter() {
+                if pass.depth() < limit {
+                    continue;
+                }
+                reached = true;
+                // SAFETY: Traversals never touch values
+                if predicate(unsafe { &(*node).val }) {
+                    return Some((unsafe { &mut (*node).val }, limit));
+                }
+            }
+            if !reached {
+                // Nothing's this deep
+                break;
+            }
+        }
+        None
+    }
+
+    /// Iterates over the values a level at a time, each level in slot order.
+    ///
+    /// There's no queue: each level is found with a fresh traversal that
+    /// turns around there, so this takes O(n × depth) time.
+    pub fn levelorder_iter_mut(&mut self) -> LevelOrderMut<'_, T, N> {
+        LevelOrderMut {
+            root: self.root,
+            pass: DfsBoundedMut::new(self.root, 0),
+            reached: false,
+        }
+    }
+
+    /// Iterates over just the values of nodes with no children, in preorder.
+    pub fn leaves_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.dfs_visits_mut()
+            .filter(|visit| visit.is_leaf)
+            .map(|visit| visit.value)
+    }
+
+    /// Like [`Self::dfs_iter_mut`] but lends out each node in turn, with a
+    /// look at its children before they're visited.
+    pub fn dfs_nodes_mut(&mut self) -> DfsNodesMut<'_, T, N> {
+        DfsNodesMut {
+            iter: NodeIter::new(self.root),
+        }
+    }
+
+    /// Calls `f` on each value in preorder. It can skip the value's children
+    /// or break out of the traversal, and either way the tree's put back
+    /// together before this returns.
+    pub fn visit_mut<B>(
+        &mut self,
+        mut f: impl FnMut(&mut T) -> ControlFlow<B, VisitAction>,
+    ) -> ControlFlow<B> {
+        let mut iter = self.dfs_iter_mut();
+        while let Some(val) = iter.next() {
+            if f(val)? == VisitAction::SkipChildren {
+                iter.skip_subtree();
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Iterates over the values in post-order, each one after everything
+    /// below it, so leaves come before their parents. This is the order
+    /// [`Drop`] frees nodes in.
+    #[doc(alias = "leaves_first_iter_mut")]
+    pub fn postorder_iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        // SAFETY: Traversals never touch values
+        NodeIter::<T, N, N>::new(self.root).map(|node| unsafe { &mut (*node).val })
+    }
+
+    /// Yields each value at the point the traversal has been through `visit`
+    /// of its node's slots: 0 is preorder, `N` is post-order and anything in
+    /// between comes after that many children. Unlike the other orders this
+    /// is picked at runtime, so every choice shares one copy of the code.
+    ///
+    /// Panics if `visit` is more than `N`.
+    pub fn dfs_iter_mut_at(&mut self, visit: usize) -> impl Iterator<Item = &mut T> {
+        assert!(visit <= N, "visit {visit} out of bounds for arity {N}");
+        let mut iter = NodeIter::<T, N, 0>::new(self.root);
+        std::iter::from_fn(move || loop {
+            let (node, visited) = iter.step()?;
+            if visited == visit {
+                // SAFETY: Traversals never touch values
+                return Some(unsafe { &mut (*node).val });
+            }
+        })
+    }
+
+    /// Like [`Self::dfs_iter_mut`] but yields the values as [`Cell`]s, so
+    /// earlier ones can still be read and written while the traversal goes on.
+    pub fn dfs_iter_cells(&mut self) -> impl Iterator<Item = &Cell<T>> {
+        self.dfs_iter_mut().map(Cell::from_mut)
+    }
+
+    /// Like [`Self::dfs_iter_mut`], reporting what the traversal does to
+    /// `observer`.
+    pub fn dfs_iter_mut_with<O: TraversalObserver>(
+        &mut self,
+        observer: O,
+    ) -> DfsIterMut<'_, T, N, O> {
+        let iter = NodeIter::with_observer(self.root, observer);
+        DfsIterMut {
+            iter,
+            root: &mut self.root,
+            last: ptr::null_mut(),
+        }
+    }
+
+    /// Makes `target` a copy of this tree, reusing its nodes where it can. See
+    /// [`Clone::clone_from`].
+    pub fn clone_into(&self, target: &mut Self)
+    where
+        T: Clone,
+    {
+        target.clone_from(self);
+    }
+
+    /// Frees the tree like dropping it would, in the same order, reporting each
+    /// node to `observer`.
+    pub fn drop_with(self, observer: impl TraversalObserver) {
+        // We want to visit the leaves first
+        let mut iter = NodeIter::<T, N, N, _>::with_observer(self.into_raw(), observer);
+        while let Some(node) = iter.next() {
+            let _ = unsafe { Box::from_raw(node) };
+            iter.observer.on_drop_node();
+        }
+    }
+
+    /// Frees the tree like dropping it would, in the same order, handing each
+    /// value to `f` just before it's dropped. Unlike a [`Drop`] impl on `T`,
+    /// `f` can borrow from its surroundings, e.g. to return resources to a
+    /// pool.
+    ///
+    /// If `f` panics the rest of the tree is leaked.
+    pub fn drop_each(self, mut f: impl FnMut(&mut T)) {
+        for node in NodeIter::<T, N, N>::new(self.into_raw()) {
+            // SAFETY: The node is finished and never reached again
+            f(unsafe { &mut (*node).val });
+            let _ = unsafe { Box::from_raw(node) };
+        }
+    }
+
+    /// Whether a traversal is partway through the tree. While one is, the
+    /// root's first slot points back up at nothing instead of a child.
+    pub(crate) fn is_traversing(&self) -> bool {
+        // SAFETY: The root itself is never moved or freed by a traversal
+        N > 0 && unsafe { self.root.as_ref() }.is_some_and(|root| root.children[0].is_seen())
+    }
+
+    /// Like the [`Debug`] output, but only descends `max_depth` levels below
+    /// the root and stops after `max_nodes` nodes. Whatever's cut off is
+    /// written as `..`.
+    pub fn debug_limited(&self, max_depth: usize, max_nodes: usize) -> DebugLimited<'_, T, N> {
+        DebugLimited {
+            tree: self,
+            max_depth,
+            max_nodes,
+        }
+    }
+
+    /// Lists every node with its address and raw child slots, for digging into
+    /// a tree that's partway through a traversal or has been corrupted. Each
+    /// slot is printed as `<0x..|seen|tag>`, with the seen bit and the spare
+    /// tag bits. Unlike [`Debug`] this is safe to use at any time, but it
+    /// allocates as much as the tree to remember where it's been.
+    ///
+    /// Slots with the seen bit set are printed but never followed, since they
+    /// may point back up the tree. A node reached twice is only printed the
+    /// first time. Anything cut off from the root by a traversal in progress
+    /// won't show up. See [`DfsIterMut::debug_dump`] for that half.
+    pub fn debug_dump(&self) -> DebugDump<'_, T, N> {
+        DebugDump {
+            root: self.root,
+            lifetime: PhantomData,
+        }
+    }
+
+    /// Moves the tree into nodes with `M` child slots each. Extra slots are left
+    /// empty and untagged. Going down in size only works when no node uses a
+    /// slot past `M`, otherwise the tree is handed back untouched. Tags on the
+    /// slots that are cut off are lost.
+    ///
+    /// Every node is reallocated, but values are moved rather than cloned and
+    /// no stack is needed.
+    pub fn try_into_arity<const M: usize>(self) -> Result<Tree<T, M>, Self> {
+        if M < N {
+            // Finished nodes have their children back in place
+            let fits = NodeIter::<T, N, N>::new(self.root).all(|node| {
+                let children = unsafe { &(*node).children };
+                children[M..].iter().all(|c| c.as_untagged().is_null())
+            });
+            if !fits {
+                return Err(self);
+            }
+        }
+        let root = self.into_raw();
+        let Some(old_root) = (unsafe { root.as_ref() }) else {
+            return Ok(Tree::empty());
+        };
+        let moved = |old: &Node<T, N>| {
+            // SAFETY: Every value is read exactly once, and its old node is
+            // freed without dropping it
+            let val = unsafe { ptr::read(&old.val) };
+            // Every slot is still in place, since the node's only just been
+            // reached
+            let mut children = [TaggedPtr::from_untagged(ptr::null_mut()); M];
+            for (slot, old) in children.iter_mut().zip(&old.children) {
+                *slot = slot.with_tag(old.tag());
+            }
+            Box::into_raw(Box::new(Node { val, children }))
+        };
+        let new_root = moved(old_root);
+
+        // The new tree's links are reversed on the way down too, with the slot
+        // we descended through holding the parent. The old tree always knows
+        // which slot that was.
+        let (mut new_prev, mut new_cur) = (ptr::null_mut::<Node<T, M>>(), new_root);
+        let mut iter = NodeIter::<T, N, 0>::new(root);
+        while let Some(cur) = unsafe { iter.cur.as_ref() } {
+            let first_unvisited = cur
+                .children
+                .iter()
+                .position(|node_ptr| !node_ptr.is_seen())
+                .unwrap_or(N);
+            if let Some(child) = cur
+                .children
+                .get(first_unvisited)
+                .and_then(|child| unsafe { child.as_untagged().as_ref() })
+            {
+                let child = moved(child);
+                let slot = unsafe { &mut (*new_cur).children[first_unvisited] };
+                *slot = slot.retarget(new_prev);
+                (new_prev, new_cur) = (new_cur, child);
+            }
+            let (node, visited) = iter.step().expect("there's a current node");
+            if visited < N {
+                continue;
+            }
+            let _ = unsafe { Box::from_raw(node as *mut ManuallyDrop<Node<T, N>>) };
+            if let Some(parent) = unsafe { iter.cur.as_ref() } {
+                let slot = parent
+                    .children
+                    .iter()
+                    .position(|node_ptr| !node_ptr.is_seen())
+                    .unwrap_or(N)
+                    - 1;
+                let parent = unsafe { &mut *new_prev };
+                let grandparent = parent.children[slot];
+                parent.children[slot] = grandparent.retarget(new_cur);
+                (new_prev, new_cur) = (grandparent.as_untagged(), parent);
+            }
+        }
+        Ok(Tree::from_raw(new_root))
+    }
+
+    /// Takes ownership of the nodes below `root`.
+    pub(crate) fn from_raw(root: *mut Node<T, N>) -> Self {
+        Self { root }
+    }
+
+    /// Gives up ownership of the nodes without freeing them.
+    pub(crate) fn into_raw(self) -> *mut Node<T, N> {
+        let root = self.root;
+        std::mem::forget(self);
+        root.cast_mut()
+    }
+}
+
+type Fence = u16;
+
+impl<const N: usize> Tree<u8, N> {
+    /// Decodes any bytes into a tree, for fuzzing. Returns the tree along with
+    /// its values in preorder. Works for any arity, so it covers
+    /// [`binary_tree`](crate::binary_tree) too.
+    pub fn arbitrary(data: &[u8]) -> (Self, Vec<u8>) {
+        Self::arbitrary_with(data, |data| {
+            let (&val, rest) = data.split_first()?;
+            *data = rest;
+            Some(val)
+        })
+    }
+}
+
+impl<T: Clone, const N: usize> Tree<T, N> {
+    /// Like [`Tree::arbitrary`], but each value is read by `decode`, which
+    /// should take what it uses off the front of the bytes it's given. Where
+    /// it returns `None` there's no node.
+    pub fn arbitrary_with(
+        data: &[u8],
+        mut decode: impl FnMut(&mut &[u8]) -> Option<T>,
+    ) -> (Self, Vec<T>) {
+        assert!(data.len() < Fence::MAX as usize, "Fence size is too small");
+        let mut values = Vec::new();
+        // The data for each child of the nodes leading to where `from_fn` is
+        // asking about, or `None` if a node had no room left for children
+        let mut regions: Vec<Option<[&[u8]; N]>> = Vec::new();
+        // Every node takes at least a byte, so this never cuts anything off
+        let tree = Self::from_fn(data.len(), |path| {
+            regions.truncate(path.len());
+            let mut data = match path.last() {
+                None => data,
+                Some(&i) => regions.last().copied().flatten()?[i],
+            };
+            let val = decode(&mut data)?;
+            values.push(val.clone());
+            regions.push(split_arbitrary(data));
+            Some(val)
+        });
+        (tree, values)
+    }
+}
+
+/// Splits what's left of a node's data between its children, at the fences
+/// found at the front. Returns `None` if there isn't enough for the fences.
+fn split_arbitrary<const N: usize>(data: &[u8]) -> Option<[&[u8]; N]> {
+    // With one child there's nothing to fence off, and without any there's
+    // nothing to split
+    let num_mid_fences = N.saturating_sub(1);
+    let (mid_fences, data) = data.split_at_checked(num_mid_fences * size_of::<Fence>())?;
+
+    // The first child starts at the front, and the rest where their fences are
+    let mut fences = [0; N];
+    for (i, v) in mid_fences.chunks(size_of::<Fence>()).enumerate() {
+        let v = Fence::from_ne_bytes(v.try_into().unwrap());
+        fences[i + 1] = v as usize % (data.len() + 1);
+    }
+    fences.sort();
+
+    Some(std::array::from_fn(|i| {
+        &data[fences[i]..fences.get(i + 1).copied().unwrap_or(data.len())]
+    }))
+}
+
+/// Reads whether the next slot in an encoded tree is filled.
+fn read_filled(reader: &mut impl Read) -> io::Result<bool> {
+    let mut filled = [0];
+    reader.read_exact(&mut filled)?;
+    match filled {
+        [0] => Ok(false),
+        [1] => Ok(true),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "slot is neither filled nor empty",
+        )),
+    }
+}
+
+fn to_ptr<T>(node: Option<Box<T>>) -> *mut T {
+    node.map(|n| Box::leak(n) as *mut _)
+        .unwrap_or(ptr::null_mut())
+}
+
+impl<T, const N: usize> Node<T, N> {
+    /// How many bits of each child link are free for tagging edges with. See
+    /// [`TaggedPtr::with_tag`].
+    pub const SPARE_TAG_BITS: u32 = TaggedPtr::<Self>::AVAILABLE_BITS;
+
+    /// The tag on child slot `i`. Tags belong to the slot, not the child in
+    /// it: they stay put while the slot is empty, when a different child is
+    /// put in it and when children are sorted, and clones copy them.
+    pub fn child_tag(&self, i: usize) -> usize {
+        self.children[i].tag()
+    }
+
+    /// Panics if `tag` doesn't fit in [`Self::SPARE_TAG_BITS`].
+    pub fn set_child_tag(&mut self, i: usize, tag: usize) {
+        self.children[i] = self.children[i].with_tag(tag);
+    }
+
+    pub fn alloc(val: T, children: [Option<Box<Node<T, N>>>; N]) -> Box<Node<T, N>> {
+        let mut converted = [TaggedPtr::from_untagged(ptr::null_mut()); N];
+        for (slot, node) in converted.iter_mut().zip(children) {
+            *slot = TaggedPtr::from_untagged(to_ptr(node));
+        }
+        Box::new(Node {
+            val,
+            children: converted,
+        })
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for Tree<T, N> {
+    /// Panics if the tree is partway through a [`Tree::dfs_iter`].
+    fn clone(&self) -> Self {
+        let mut copy = Self::empty();
+        copy.clone_from(self);
+        copy
+    }
+
+    /// Copies `source` in lockstep with a traversal of it, so it needs no
+    /// stack or recursion. Nodes already in `self` are reused wherever the
+    /// shapes line up, and only the difference is allocated or freed.
+    ///
+    /// Panics if `source` is partway through a [`Tree::dfs_iter`].
+    fn clone_from(&mut self, source: &Self) {
+        assert!(!source.is_traversing(), "tree is being iterated over");
+        // SAFETY: No traversal is in progress so the root is in place
+        let Some(root) = (unsafe { source.root.as_ref() }) else {
+            *self = Self::empty();
+            return;
+        };
+        match unsafe { self.root.cast_mut().as_mut() } {
+            Some(own) => own.val.clone_from(&root.val),
+            None => self.root = Box::into_raw(Node::alloc(root.val.clone(), [const { None }; N])),
+        }
+        // Both trees go through the same link reversal one step at a time. If
+        // anything panics they're put back together as these are dropped.
+        let mut src = NodeIter::<T, N, 0>::new(source.root);
+        let mut dst = NodeIter::<T, N, 0>::new(self.root);
+        while let Some(cur) = unsafe { src.cur.as_ref() } {
+            let first_unvisited = cur
+                .children
+                .iter()
+                .position(|node_ptr| !node_ptr.is_seen())
+                .unwrap_or(N);
+            if first_unvisited < N {
+                // Make our child match the one we're about to descend into,
+                // so that stepping us does the same as stepping the source
+                let child = unsafe { cur.children[first_unvisited].as_untagged().as_ref() };
+                // SAFETY: We're always at the mirror image of `cur`
+                let slot = unsafe { &mut (*dst.cur).children[first_unvisited] };
+                *slot = slot.with_tag(cur.children[first_unvisited].tag());
+                match (child, unsafe { slot.as_untagged().as_mut() }) {
+                    (Some(child), Some(own)) => own.val.clone_from(&child.val),
+                    (Some(child), None) => {
+                        let child = Node::alloc(child.val.clone(), [const { None }; N]);
+                        *slot = slot.retarget(Box::into_raw(child));
+                    }
+                    (None, Some(own)) => {
+                        *slot = slot.retarget(ptr::null_mut());
+                        drop(Tree::from_raw(own));
+                    }
+                    (None, None) => {}
+                }
+            }
+            src.step();
+            dst.step();
+        }
+    }
+}
+
+/// Values are dropped in post-order, so every node's children go before it
+/// (first slot to last) and a value's parent is always still around while it's
+/// being dropped. This is guaranteed, as it is for [`Tree::drop_with`].
+impl<T, const N: usize> Drop for Tree<T, N> {
+    fn drop(&mut self) {
+        // We want to visit the leaves first
+        let iter = NodeIter::<T, N, N>::new(self.root);
+        for node in iter {
+            let _ = unsafe { Box::from_raw(node) };
+        }
+    }
+}
+
+pub(crate) struct NodeIter<
+    'tree,
+    T,
+    const N: usize,
+    const RETURN_ON_VISIT: usize,
+    O: TraversalObserver = (),
+> {
+    prev: *mut Node<T, N>,
+    cur: *mut Node<T, N>,
+    // Doesn't mention `T` so that an owning iterator can use `'static`
+    lifetime: PhantomData<&'tree ()>,
+    pub(crate) observer: O,
+}
+
+// NOTE: It's okay if this doesn't run. The tree will leak some nodes but be
+// safe
+impl<'tree, T, const N: usize, const RETURN_ON_VISIT: usize, O: TraversalObserver> Drop
+    for NodeIter<'tree, T, N, RETURN_ON_VISIT, O>
+{
+    fn drop(&mut self) {
+        // Ascend the tree until we reach the top (i.e. null self.cur) and
+        while let Some(cur) = unsafe { self.cur.as_mut() } {
+            self.observer.on_repair();
+            let first_unvisited = cur
+                .children
+                .iter()
+                .position(|node_ptr| !node_ptr.is_seen())
+                .unwrap_or(N);
+
+            if first_unvisited == 0 {
+                // If we haven't visited any children, then our previous node is our parent
+                self.cur = self.prev;
+                self.prev = cur;
+            } else {
+                let parent = cur.children[0];
+                // Shift the pointers back, leaving each slot's tag where it is
+                for i in 0..(first_unvisited - 1) {
+                    cur.children[i] = cur.children[i].retarget(cur.children[i + 1].as_untagged());
+                }
+                let last = &mut cur.children[first_unvisited - 1];
+                *last = last.retarget(self.prev);
+                self.cur = parent.as_untagged();
+                self.prev = cur;
+            }
+        }
+    }
+}
+
+impl<'tree, T, const N: usize, const RETURN_ON_VISIT: usize>
+    NodeIter<'tree, T, N, RETURN_ON_VISIT>
+{
+    pub(crate) fn new(root: *const Node<T, N>) -> Self {
+        Self::with_observer(root, ())
+    }
+}
+
+impl<'tree, T, const N: usize, const RETURN_ON_VISIT: usize, O: TraversalObserver>
+    NodeIter<'tree, T, N, RETURN_ON_VISIT, O>
+{
+    pub(crate) fn with_observer(root: *const Node<T, N>, observer: O) -> Self {
+        Self {
+            prev: ptr::null_mut(),
+            cur: root.cast_mut(),
+            lifetime: PhantomData,
+            observer,
+        }
+    }
+
+    /// Like [`Tree::debug_dump`], but starting at the node the traversal is
+    /// about to step through. Together with dumping the tree this covers both
+    /// halves of the tree while it's split by a traversal.
+    fn debug_dump(&self) -> DebugDump<'_, T, N> {
+        DebugDump {
+            root: self.cur,
+            lifetime: PhantomData,
+        }
+    }
+
+    /// Advances the traversal by a single step, yielding the node stepped
+    /// through and how many of its children had been visited beforehand.
+    ///
+    /// When that count is `N` the node has just been finished: its links are
+    /// restored and it's waiting in `prev` to be stored back into its parent.
+    pub(crate) fn step(&mut self) -> Option<(*mut Node<T, N>, usize)> {
+        self.advance(true)
+    }
+
+    /// Like [`Self::step`], but if the step would go down into a child it
+    /// passes over it instead, as though the child had already been finished.
+    pub(crate) fn step_over(&mut self) -> Option<(*mut Node<T, N>, usize)> {
+        self.advance(false)
+    }
+
+    fn advance(&mut self, descend: bool) -> Option<(*mut Node<T, N>, usize)> {
+        // SAFETY: We're guarnteed the pointers live for the lifespan of 'tree
+        let cur: &mut Node<T, N> = unsafe { self.cur.as_mut()? };
+
+        let first_unvisited = cur
+            .children
+            .iter()
+            .position(|node_ptr| !node_ptr.is_seen())
+            .unwrap_or(N);
+        if first_unvisited == 0 {
+            self.observer.on_enter();
+        }
+        if first_unvisited == N {
+            self.observer.on_leave();
+        }
+        if first_unvisited < N {
+            // Visit that child
+            let child_to_visit = cur.children[first_unvisited].as_untagged();
+            if cfg!(debug_assertions) && descend {
+                check_unvisited(child_to_visit);
+            }
+            let slot = &mut cur.children[first_unvisited];
+            *slot = slot.retarget(self.prev).seen();
+            if child_to_visit.is_null() || !descend {
+                // Return like we just visited this node
+                self.prev = child_to_visit;
+            } else {
+                self.cur = child_to_visit;
+                self.prev = cur;
+            }
+        } else {
+            // Visited all children, go re-construct things and go up.
+            if first_unvisited == 0 {
+                // If we haven't visited any children, then our previous node is our parent
+                self.cur = self.prev;
+                self.prev = cur;
+            } else {
+                let parent = cur.children[0];
+                // Shift the pointers back, leaving each slot's tag where it is
+                for i in 0..(first_unvisited - 1) {
+                    cur.children[i] = cur.children[i].retarget(cur.children[i + 1].as_untagged());
+                }
+                let last = &mut cur.children[first_unvisited - 1];
+                *last = last.retarget(self.prev);
+                self.cur = parent.as_untagged();
+                self.prev = cur;
+            }
+        }
+        Some((cur, first_unvisited))
+    }
+
+    /// The original first child of `node`, whose slot is holding the parent
+    /// for now.
+    ///
+    /// Only valid directly after [`Self::step`] stepped into `node` with a
+    /// visit count of 0.
+    pub(crate) fn first_child(&self, node: *mut Node<T, N>) -> *mut Node<T, N> {
+        // If the first child was null we never left the node
+        if N == 0 || ptr::eq(self.cur, node) {
+            ptr::null_mut()
+        } else {
+            self.cur
+        }
+    }
+
+    /// Child `i` of `node`, looking past the parent in the first slot.
+    ///
+    /// Only valid directly after [`Self::step`] stepped into `node` with a
+    /// visit count of 0.
+    pub(crate) fn entered_child(&self, node: *mut Node<T, N>, i: usize) -> *mut Node<T, N> {
+        assert!(i < N, "child index {i} out of bounds for arity {N}");
+        if i == 0 {
+            self.first_child(node)
+        } else {
+            // SAFETY: The node was just stepped into so it's live
+            unsafe { (*node).children[i].as_untagged() }
+        }
+    }
+
+    /// How many of `node`'s slots are filled. Valid at the same times as
+    /// [`Self::entered_child`].
+    pub(crate) fn entered_arity(&self, node: *mut Node<T, N>) -> usize {
+        (0..N)
+            .filter(|&i| !self.entered_child(node, i).is_null())
+            .count()
+    }
+
+    /// Finishes `node` immediately without visiting any of its children.
+    ///
+    /// Only valid directly after [`Self::step`] stepped into `node` with a
+    /// visit count of 0.
+    pub(crate) fn skip_children(&mut self, node: *mut Node<T, N>) {
+        if N == 0 {
+            // The step already finished it
+            return;
+        }
+        let first_child = self.first_child(node);
+        // SAFETY: We've only just stepped into this node so it's valid
+        let node = unsafe { &mut *node };
+        let parent = node.children[0];
+        node.children[0] = parent.retarget(first_child);
+        self.cur = parent.as_untagged();
+        self.prev = node;
+        self.observer.on_leave();
+    }
+
+    /// Puts `new` into child slot `i` of `node`, returning what was there. The
+    /// traversal goes on into `new` as though it had always been there.
+    ///
+    /// Only valid at the same times as [`Self::entered_child`].
+    pub(crate) fn graft_entered(
+        &mut self,
+        node: *mut Node<T, N>,
+        i: usize,
+        new: *mut Node<T, N>,
+    ) -> *mut Node<T, N> {
+        let old = self.entered_child(node, i);
+        if i == 0 {
+            // The first slot's been stepped through already, so swap where
+            // that step went instead
+            if new.is_null() {
+                self.cur = node;
+                self.prev = ptr::null_mut();
+            } else {
+                self.cur = new;
+                self.prev = node;
+            }
+        } else {
+            // SAFETY: The node was just stepped into so it's live
+            let slot = unsafe { &mut (*node).children[i] };
+            *slot = slot.retarget(new);
+        }
+        old
+    }
+
+    /// Swaps `node` for `new` without visiting either of them any further.
+    /// Returns whether `node` was the root.
+    ///
+    /// Only valid directly after [`Self::step`] stepped into `node` with a
+    /// visit count of 0.
+    pub(crate) fn replace_entered(&mut self, node: *mut Node<T, N>, new: *mut Node<T, N>) -> bool {
+        self.skip_children(node);
+        self.prev = new;
+        self.cur.is_null()
+    }
+
+    /// Swaps out the node that the last step finished for `new`, which the
+    /// parent will adopt in its place. Returns the finished node.
+    ///
+    /// Only valid directly after [`Self::step`] reported a visit count of `N`.
+    pub(crate) fn replace_finished(&mut self, new: *mut Node<T, N>) -> *mut Node<T, N> {
+        std::mem::replace(&mut self.prev, new)
+    }
+
+    /// The (possibly replaced) root of the tree once the traversal is over.
+    pub(crate) fn finished_root(&self) -> *mut Node<T, N> {
+        debug_assert!(self.cur.is_null(), "traversal is still in progress");
+        self.prev
+    }
+}
+
+/// Panics if `node` is already partway through being traversed, which is what
+/// happens when a cycle or a node shared with one of its descendants gets fed
+/// in as a tree. Checking before stepping in means the iterator can still put
+/// the links back as it unwinds.
+///
+/// Nodes shared between otherwise unrelated subtrees look fresh each time and
+/// aren't caught.
+fn check_unvisited<T, const N: usize>(node: *mut Node<T, N>) {
+    // SAFETY: Only called on live nodes about to be stepped into
+    let Some(node_ref) = (unsafe { node.as_ref() }) else {
+        return;
+    };
+    assert!(
+        !node_ref.children.iter().any(|c| c.is_seen()),
+        "node {node:p} was reached twice, so this isn't a tree",
+    );
+}
+
+/// Walks up from a node whose children are mid-traversal, yielding each
+/// ancestor along with the child slot we descended through to get here.
+///
+/// # Safety
+///
+/// `node` must currently be on the traversal's path, i.e. stepped into but not
+/// yet finished, and the tree must not be stepped while this is alive.
+pub(crate) unsafe fn ancestors<T, const N: usize>(
+    node: *mut Node<T, N>,
+) -> impl Iterator<Item = (*mut Node<T, N>, usize)> {
+    let mut cur = node;
+    std::iter::from_fn(move || {
+        if N == 0 {
+            return None;
+        }
+        // While a node is in progress its first slot holds its parent
+        let parent = unsafe { (*cur).children[0].as_untagged().as_mut()? };
+        let visited = parent
+            .children
+            .iter()
+            .position(|node_ptr| !node_ptr.is_seen())
+            .unwrap_or(N);
+        cur = parent;
+        Some((cur, visited - 1))
+    })
+}
+
+impl<'tree, T, const N: usize, const RETURN_ON_VISIT: usize, O: TraversalObserver> Iterator
+    for NodeIter<'tree, T, N, RETURN_ON_VISIT, O>
+{
+    type Item = *mut Node<T, N>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, first_unvisited) = self.step()?;
+            if first_unvisited == RETURN_ON_VISIT {
+                return Some(node);
+            }
+        }
+    }
+}
+
+/// Can be sent to another thread along with its borrow of the tree.
+///
+/// Hands out `&mut T`, so unlike [`Tree`] this is invariant in `T`:
+///
+/// ```compile_fail
+/// use constant_size_dfs::array_tree::DfsIterMut;
+/// fn shorten<'a, 'i>(iter: DfsIterMut<'i, &'static str, 2>) -> DfsIterMut<'i, &'a str, 2> {
+///     iter
+/// }
+/// ```
+pub struct DfsIterMut<'tree, T, const N: usize, O: TraversalObserver = ()> {
+    iter: NodeIter<'tree, T, N, 0, O>,
+    root: &'tree mut *const Node<T, N>,
+    /// The node we last yielded, if it's still in the tree
+    last: *mut Node<T, N>,
+}
+
+// SAFETY: The tree is borrowed mutably, so nothing else can look at it
+unsafe impl<T: Send, const N: usize, O: TraversalObserver + Send> Send for DfsIterMut<'_, T, N, O> {}
+
+impl<'tree, T, const N: usize, O: TraversalObserver> DfsIterMut<'tree, T, N, O> {
+    /// Dumps the part of the tree that's been cut off from the root by
+    /// iterating, starting at the next node to be stepped through. See
+    /// [`Tree::debug_dump`].
+    pub fn debug_dump(&self) -> DebugDump<'_, T, N> {
+        self.iter.debug_dump()
+    }
+
+    /// Carries on past the node that was just yielded without visiting
+    /// anything below it.
+    ///
+    /// Panics if nothing's been yielded since the last call to this or
+    /// [`Self::replace`].
+    pub fn skip_subtree(&mut self) {
+        let node = std::mem::replace(&mut self.last, ptr::null_mut());
+        assert!(!node.is_null(), "no value was just yielded to skip below");
+        self.iter.skip_children(node);
+    }
+
+    /// Replaces the node that was just yielded, along with everything below it,
+    /// with `subtree`. Returns the replaced subtree.
+    ///
+    /// Iteration carries on after the replacement without visiting any of it.
+    /// Taking the yielded reference back is what makes it safe to hand out the
+    /// replaced nodes.
+    ///
+    /// Panics if `current` isn't the value most recently yielded.
+    pub fn replace(&mut self, current: &'tree mut T, subtree: Tree<T, N>) -> Tree<T, N> {
+        let node = self.last;
+        // SAFETY: `last` is only set to nodes we've just stepped into
+        let is_last = unsafe { node.as_ref() }.is_some_and(|n| ptr::eq(&n.val, current));
+        assert!(is_last, "can only replace the value that was just yielded");
+        self.last = ptr::null_mut();
+        let new = subtree.into_raw();
+        if self.iter.replace_entered(node, new) {
+            *self.root = new;
+        }
+        Tree::from_raw(node)
+    }
+}
+
+impl<'tree, T, const N: usize, O: TraversalObserver> Iterator for DfsIterMut<'tree, T, N, O> {
+    type Item = &'tree mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.last = ptr::null_mut();
+        let node = self.iter.next()?;
+        self.last = node;
+        Some(unsafe { &mut node.as_mut().expect("should not be null").val })
+    }
+}
+
+/// Every step of a traversal. Returned by [`Tree::raw_walk`].
+pub struct RawWalk<'tree, T, const N: usize> {
+    iter: NodeIter<'tree, T, N, 0>,
+}
+
+impl<'tree, T: 'tree, const N: usize> Iterator for RawWalk<'tree, T, N> {
+    type Item = RawVisit<'tree, T, N>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, visited) = self.iter.step()?;
+        Some(RawVisit {
+            node,
+            visited,
+            lifetime: PhantomData,
+        })
+    }
+}
+
+/// One step of a [`RawWalk`]: a node and how many of its slots the traversal
+/// has been through.
+///
+/// The node's links are rearranged while the traversal is in it, so all there
+/// is safe access to is what a traversal leaves alone.
+pub struct RawVisit<'tree, T, const N: usize> {
+    node: *mut Node<T, N>,
+    visited: usize,
+    lifetime: PhantomData<&'tree T>,
+}
+
+impl<T, const N: usize> Clone for RawVisit<'_, T, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, const N: usize> Copy for RawVisit<'_, T, N> {}
+
+impl<'tree, T, const N: usize> RawVisit<'tree, T, N> {
+    /// 0 when the node's just been entered, `N` when it's been finished and
+    /// the number of slots gone through in between.
+    pub fn visited(&self) -> usize {
+        self.visited
+    }
+
+    /// Tells nodes apart. Stays the same for as long as the node is in a tree.
+    pub fn id(&self) -> usize {
+        self.node as usize
+    }
+
+    pub fn value(&self) -> &'tree T {
+        // SAFETY: Traversals never touch values
+        unsafe { &(*self.node).val }
+    }
+
+    /// See [`Node::child_tag`].
+    pub fn child_tag(&self, i: usize) -> usize {
+        // SAFETY: Tags stay on their slots partway through a traversal
+        unsafe { (*self.node).child_tag(i) }
+    }
+
+    /// # Safety
+    ///
+    /// Nothing else may be borrowing the value, including any earlier
+    /// [`Self::value`] of the same node.
+    pub unsafe fn value_mut(&self) -> &'tree mut T {
+        unsafe { &mut (*self.node).val }
+    }
+
+    /// The node itself. While the traversal is in it, the slot it's about to
+    /// go through and the ones before hold the parent and the children it's
+    /// been through, shifted over by one. Any changes have to be undone before
+    /// the walk takes another step.
+    pub fn as_ptr(&self) -> *mut Node<T, N> {
+        self.node
+    }
+}
+
+/// A preorder traversal that turns around at `max_depth`, with the root at
+/// depth 0. Returned by [`Tree::dfs_iter_mut_bounded`].
+pub struct DfsBoundedMut<'tree, T, const N: usize> {
+    iter: NodeIter<'tree, T, N, 0>,
+    max_depth: usize,
+    /// How many nodes the traversal is inside of
+    inside: usize,
+    /// The depth of the value last yielded
+    last_depth: usize,
+}
+
+impl<T, const N: usize> DfsBoundedMut<'_, T, N> {
+    fn new(root: *const Node<T, N>, max_depth: usize) -> Self {
+        Self {
+            iter: NodeIter::new(root),
+            max_depth,
+            inside: 0,
+            last_depth: 0,
+        }
+    }
+
+    /// How deep the value last yielded is.
+    pub fn depth(&self) -> usize {
+        self.last_depth
+    }
+
+    /// Steps on to the next node no deeper than `max_depth`, leaving its value
+    /// alone so that callers only borrow the values they hand out.
+    fn enter(&mut self) -> Option<*mut Node<T, N>> {
+        loop {
+            let (node, visited) = self.iter.step()?;
+            // With no slots a node is entered and left in the same step
+            let mut skipped = false;
+            if visited == 0 {
+                self.last_depth = self.inside;
+                if self.inside < self.max_depth {
+                    self.inside += 1;
+                } else {
+                    self.iter.skip_children(node);
+                    skipped = true;
+                }
+            }
+            if visited == N && !skipped {
+                self.inside -= 1;
+            }
+            if visited == 0 {
+                return Some(node);
+            }
+        }
+    }
+}
+
+impl<'tree, T: 'tree, const N: usize> Iterator for DfsBoundedMut<'tree, T, N> {
+    type Item = &'tree mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.enter()?;
+        // SAFETY: Traversals never touch values
+        Some(unsafe { &mut (*node).val })
+    }
+}
+
+/// Returned by [`Tree::levelorder_iter_mut`].
+pub struct LevelOrderMut<'tree, T, const N: usize> {
+    root: *const Node<T, N>,
+    /// The pass over the current level and everything above it
+    pass: DfsBoundedMut<'tree, T, N>,
+    /// Whether the current pass has got down to its level anywhere
+    reached: bool,
+}
+
+impl<'tree, T: 'tree, const N: usize> Iterator for LevelOrderMut<'tree, T, N> {
+    type Item = &'tree mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Shallower nodes come up again on every pass, and their values may
+            // still be borrowed from earlier ones, so they're only stepped over
+            match self.pass.enter() {
+                Some(node) if self.pass.last_depth == self.pass.max_depth => {
+                    self.reached = true;
+                    // SAFETY: Each pass only hands out its own level, and
+                    // traversals never touch values
+                    return Some(unsafe { &mut (*node).val });
+                }
+                Some(_) => continue,
+                None if self.reached => {
+                    let level = self.pass.max_depth + 1;
+                    self.pass = DfsBoundedMut::new(self.root, level);
+                    self.reached = false;
+                }
+                None => return None,
+            }
+        }
+    }
+}
+
+/// Returned by [`Tree::dfs_iter_mut_best_first`].
+///
+/// Children are visited in any order, so the reversed links can't be told
+/// apart by position like [`NodeIter`]'s are. Instead, every slot that's been
+/// visited is marked seen and the one leading down to the current child holds
+/// the parent. That slot is the only seen one not pointing at a finished node,
+/// since the parent is still in progress itself (or null at the root).
+pub struct DfsBestFirstMut<'tree, T, const N: usize, F> {
+    prev: *mut Node<T, N>,
+    cur: *mut Node<T, N>,
+    compare: F,
+    lifetime: PhantomData<&'tree mut T>,
+}
+
+/// Whether `node` has been stepped into by a traversal and not yet finished.
+fn in_progress<T, const N: usize>(node: *const Node<T, N>) -> bool {
+    // SAFETY: Only the slots are read, never the value, which may be borrowed
+    unsafe { (*node).children.iter().any(|slot| slot.is_seen()) }
+}
+
+impl<T, const N: usize, F> DfsBestFirstMut<'_, T, N, F> {
+    /// The slot of `node` holding its parent, or `None` if `node` hasn't been
+    /// stepped into yet.
+    fn parent_slot(node: *mut Node<T, N>) -> Option<usize> {
+        // SAFETY: `node` is live and we only read its slots
+        let children = unsafe { &(*node).children };
+        children.iter().position(|slot| {
+            let up = slot.as_untagged();
+            slot.is_seen() && (up.is_null() || in_progress(up))
+        })
+    }
+
+    /// Puts the links of finished `node` back and goes up to its parent.
+    fn finish(&mut self, node: *mut Node<T, N>, parent_slot: usize) {
+        // SAFETY: `node` is live and the user only ever holds its value
+        let children = unsafe { &mut (*node).children };
+        let parent = children[parent_slot].as_untagged();
+        children[parent_slot] = children[parent_slot].retarget(self.prev);
+        for slot in children.iter_mut() {
+            *slot = slot.retarget(slot.as_untagged());
+        }
+        self.cur = parent;
+        self.prev = node;
+    }
+}
+
+impl<T, const N: usize, F: FnMut(&T, &T) -> Ordering> DfsBestFirstMut<'_, T, N, F> {
+    /// The best non-null child of `node` that hasn't been visited yet.
+    fn best_unvisited(&mut self, node: *mut Node<T, N>) -> Option<usize> {
+        // SAFETY: `node` is live and we only read its slots
+        let children = unsafe { &(*node).children };
+        let mut best: Option<usize> = None;
+        for (i, slot) in children.iter().enumerate() {
+            if slot.is_seen() || slot.as_untagged().is_null() {
+                continue;
+            }
+            // SAFETY: Unvisited children haven't been handed out, and nothing
+            // moves while `compare` runs
+            let better = best.is_none_or(|best| unsafe {
+                let best = &(*children[best].as_untagged()).val;
+                (self.compare)(&(*slot.as_untagged()).val, best) == Ordering::Less
+            });
+            if better {
+                best = Some(i);
+            }
+        }
+        best
+    }
+}
+
+impl<'tree, T: 'tree, const N: usize, F: FnMut(&T, &T) -> Ordering> Iterator
+    for DfsBestFirstMut<'tree, T, N, F>
+{
+    type Item = &'tree mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.cur;
+            if node.is_null() {
+                return None;
+            }
+            let parent_slot = Self::parent_slot(node);
+            // Pick before touching any links so a panicking `compare` leaves
+            // them for `drop` to put back
+            let Some(next) = self.best_unvisited(node) else {
+                match parent_slot {
+                    Some(parent_slot) => self.finish(node, parent_slot),
+                    None => {
+                        // No children to go into, so we're done here already
+                        self.cur = self.prev;
+                        self.prev = node;
+                        return Some(unsafe { &mut (*node).val });
+                    }
+                }
+                continue;
+            };
+            // SAFETY: `node` is live and the user only ever holds its value
+            let children = unsafe { &mut (*node).children };
+            let parent = match parent_slot {
+                Some(parent_slot) => {
+                    let slot = &mut children[parent_slot];
+                    let parent = slot.as_untagged();
+                    *slot = slot.retarget(self.prev).seen();
+                    parent
+                }
+                None => self.prev,
+            };
+            let slot = &mut children[next];
+            self.cur = slot.as_untagged();
+            *slot = slot.retarget(parent).seen();
+            self.prev = node;
+            if parent_slot.is_none() {
+                // SAFETY: Traversals never touch values
+                return Some(unsafe { &mut (*node).val });
+            }
+        }
+    }
+}
+
+/// Puts back the links of every node that's still in progress. Unlike
+/// finishing the traversal, this never calls `compare`.
+impl<T, const N: usize, F> Drop for DfsBestFirstMut<'_, T, N, F> {
+    fn drop(&mut self) {
+        while !self.cur.is_null() {
+            let node = self.cur;
+            match Self::parent_slot(node) {
+                Some(parent_slot) => self.finish(node, parent_slot),
+                // Not stepped into yet, so `prev` is its parent
+                None => {
+                    self.cur = self.prev;
+                    self.prev = node;
+                }
+            }
+        }
+    }
+}
+
+/// Returned by [`Tree::dfs_iter_mut_shuffled`] and
+/// [`Tree::dfs_iter_mut_rev_children`].
+pub struct DfsReorderedMut<'tree, T, const N: usize> {
+    iter: NodeIter<'tree, T, N, 0>,
+    order: ChildOrder,
+}
+
+/// How a [`DfsReorderedMut`] rearranges child slots. Each has to be undoable
+/// once the node's finished.
+enum ChildOrder {
+    /// `path` sums up the slots leading down to the current node, and is
+    /// where each node's shuffle comes from.
+    Shuffled {
+        seed: u64,
+        path: u64,
+        depth: usize,
+    },
+    Reversed,
+}
+
+impl ChildOrder {
+    /// What going through `slot` down to `depth` adds to a path.
+    fn path_step(depth: usize, slot: usize) -> u64 {
+        mix((depth as u64) << 32 | slot as u64)
+    }
+
+    fn descend(&mut self, slot: usize) {
+        if let Self::Shuffled { path, depth, .. } = self {
+            *depth += 1;
+            *path = path.wrapping_add(Self::path_step(*depth, slot));
+        }
+    }
+
+    fn ascend(&mut self, slot: usize) {
+        if let Self::Shuffled { path, depth, .. } = self {
+            *path = path.wrapping_sub(Self::path_step(*depth, slot));
+            *depth -= 1;
+        }
+    }
+}
+
+/// SplitMix64's finalizer.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The slot that Fisher-Yates swaps with slot `i`. It only depends on the
+/// node's path and `i`, so the shuffle can be undone without remembering it.
+fn swap_for(seed: u64, path: u64, i: usize) -> usize {
+    let key = mix(seed ^ mix(path ^ i as u64));
+    (key % (i as u64 + 1)) as usize
+}
+
+impl<'tree, T, const N: usize> DfsReorderedMut<'tree, T, N> {
+    fn new(root: *const Node<T, N>, order: ChildOrder) -> Self {
+        let iter = Self {
+            iter: NodeIter::new(root),
+            order,
+        };
+        iter.reorder(root.cast_mut());
+        iter
+    }
+
+    /// Rearranges the child slots of a node that's about to be stepped into.
+    fn reorder(&self, node: *mut Node<T, N>) {
+        // SAFETY: Nothing's been moved into the node's slots yet
+        let Some(node_ref) = (unsafe { node.as_mut() }) else {
+            return;
+        };
+        match self.order {
+            ChildOrder::Shuffled { seed, path, .. } => {
+                for i in (1..N).rev() {
+                    node_ref.children.swap(i, swap_for(seed, path, i));
+                }
+            }
+            ChildOrder::Reversed => node_ref.children.reverse(),
+        }
+    }
+
+    /// Puts a finished node's children back in their original slots.
+    fn restore(&self, node: *mut Node<T, N>) {
+        // SAFETY: The node's finished so its slots are back as we arranged them
+        let node_ref = unsafe { &mut *node };
+        match self.order {
+            ChildOrder::Shuffled { seed, path, .. } => {
+                for i in 1..N {
+                    node_ref.children.swap(i, swap_for(seed, path, i));
+                }
+            }
+            ChildOrder::Reversed => node_ref.children.reverse(),
+        }
+    }
+
+    fn step(&mut self) -> Option<(*mut Node<T, N>, usize)> {
+        let (node, visited) = self.iter.step()?;
+        if visited < N && !ptr::eq(self.iter.cur, node) {
+            // Stepped down into the child in slot `visited`
+            self.order.descend(visited);
+            self.reorder(self.iter.cur);
+        }
+        if visited == N {
+            self.restore(node);
+            let parent = self.iter.cur;
+            if !parent.is_null() {
+                // We came up out of the last slot it's been through. Only its
+                // slots are read, since its value may be borrowed.
+                let children = unsafe { &(*parent).children };
+                let seen = children.iter().take_while(|c| c.is_seen()).count();
+                self.order.ascend(seen - 1);
+            }
+        }
+        Some((node, visited))
+    }
+}
+
+impl<'tree, T: 'tree, const N: usize> Iterator for DfsReorderedMut<'tree, T, N> {
+    type Item = &'tree mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, visited) = self.step()?;
+            if visited == 0 {
+                // SAFETY: Traversals never touch values
+                return Some(unsafe { &mut (*node).val });
+            }
+        }
+    }
+}
+
+/// Finishes the traversal so every node gets put back in order, which takes as
+/// long as the rest of the traversal would have.
+impl<T, const N: usize> Drop for DfsReorderedMut<'_, T, N> {
+    fn drop(&mut self) {
+        while self.step().is_some() {}
+    }
+}
+
+/// A value along with how many children its node had when it was visited.
+#[derive(Debug)]
+pub struct Visit<'tree, T> {
+    pub value: &'tree mut T,
+    pub non_null_children: usize,
+    pub is_leaf: bool,
+}
+
+/// Returned by [`Tree::dfs_visits_mut`].
+pub struct DfsVisitsMut<'tree, T, const N: usize> {
+    iter: NodeIter<'tree, T, N, 0>,
+}
+
+impl<'tree, T: 'tree, const N: usize> Iterator for DfsVisitsMut<'tree, T, N> {
+    type Item = Visit<'tree, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.iter.next()?;
+        let non_null_children = self.iter.entered_arity(node);
+        Some(Visit {
+            // SAFETY: Traversals never touch values
+            value: unsafe { &mut (*node).val },
+            non_null_children,
+            is_leaf: non_null_children == 0,
+        })
+    }
+}
+
+/// An iterator whose items borrow from the iterator itself, so each one has to
+/// be let go of before the next is asked for.
+pub trait LendingIterator {
+    type Item<'a>
+    where
+        Self: 'a;
+
+    fn next(&mut self) -> Option<Self::Item<'_>>;
+}
+
+/// Hands out each node in preorder as a [`NodeMut`]. Returned by
+/// [`Tree::dfs_nodes_mut`].
+pub struct DfsNodesMut<'tree, T, const N: usize> {
+    iter: NodeIter<'tree, T, N, 0>,
+}
+
+impl<'tree, T, const N: usize> LendingIterator for DfsNodesMut<'tree, T, N> {
+    type Item<'a>
+        = NodeMut<'a, 'tree, T, N>
+    where
+        Self: 'a;
+
+    fn next(&mut self) -> Option<NodeMut<'_, 'tree, T, N>> {
+        let node = self.iter.next()?;
+        Some(NodeMut {
+            iter: &mut self.iter,
+            node,
+        })
+    }
+}
+
+/// Lends out each value in preorder along with its parent's. Returned by
+/// [`Tree::dfs_with_parent_mut`].
+///
+/// This is a [`LendingIterator`] since the parent's value was handed out on
+/// its own turn, so that has to be let go of first.
+pub struct DfsWithParentMut<'tree, T, const N: usize> {
+    iter: NodeIter<'tree, T, N, 0>,
+}
+
+impl<'tree, T, const N: usize> LendingIterator for DfsWithParentMut<'tree, T, N> {
+    type Item<'a>
+        = (Option<&'a T>, &'a mut T)
+    where
+        Self: 'a;
+
+    fn next(&mut self) -> Option<(Option<&'_ T>, &'_ mut T)> {
+        let node = self.iter.next()?;
+        // SAFETY: We've just stepped into the node, so its first slot is
+        // holding the parent. Traversals never touch values, and the parent
+        // and child are distinct.
+        let parent = match N {
+            0 => None,
+            _ => unsafe { (*node).children[0].as_untagged().as_ref() }.map(|p| &p.val),
+        };
+        Some((parent, unsafe { &mut (*node).val }))
+    }
+}
+
+/// What [`Tree::visit_mut`] should do after visiting a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisitAction {
+    Continue,
+    SkipChildren,
+}
+
+/// What a [`WalkMut`] has just done.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event<'a, T> {
+    /// It's entered a node, and none of the node's children have been visited.
+    Enter(&'a mut T),
+    /// It's done with child slot `i` of the node it's in, filled or not.
+    ChildDone(usize),
+    /// It's done with every slot of a node and is about to leave it.
+    Exit(&'a mut T),
+}
+
+/// Lends out an [`Event`] for every step of a preorder traversal. Returned by
+/// [`Tree::walk_mut`].
+pub struct WalkMut<'tree, T, const N: usize> {
+    iter: NodeIter<'tree, T, N, 0>,
+    /// The node to exit before stepping again, if any
+    exit: *mut Node<T, N>,
+}
+
+impl<'tree, T, const N: usize> LendingIterator for WalkMut<'tree, T, N> {
+    type Item<'a>
+        = Event<'a, T>
+    where
+        Self: 'a;
+
+    fn next(&mut self) -> Option<Event<'_, T>> {
+        // SAFETY: Traversals never touch values, and only one is lent out at
+        // a time
+        if let Some(node) = unsafe { std::mem::replace(&mut self.exit, ptr::null_mut()).as_mut() } {
+            return Some(Event::Exit(&mut node.val));
+        }
+        let (node, visited) = self.iter.step()?;
+        if visited == N {
+            self.exit = node;
+        }
+        Some(match visited {
+            0 => Event::Enter(unsafe { &mut (*node).val }),
+            _ => Event::ChildDone(visited - 1),
+        })
+    }
+}
+
+/// The node a [`DfsNodesMut`] just stepped into. None of its children have been
+/// visited yet, so they're all still attached.
+pub struct NodeMut<'a, 'tree, T, const N: usize> {
+    iter: &'a mut NodeIter<'tree, T, N, 0>,
+    node: *mut Node<T, N>,
+}
+
+impl<T, const N: usize> NodeMut<'_, '_, T, N> {
+    fn child_ptr(&self, i: usize) -> *mut Node<T, N> {
+        self.iter.entered_child(self.node, i)
+    }
+
+    pub fn value(&self) -> &T {
+        // SAFETY: Traversals never touch values
+        unsafe { &(*self.node).val }
+    }
+
+    pub fn value_mut(&mut self) -> &mut T {
+        unsafe { &mut (*self.node).val }
+    }
+
+    /// The value of child `i`, which won't be visited until this is let go.
+    ///
+    /// Panics if `i` isn't less than `N`.
+    pub fn child(&self, i: usize) -> Option<&T> {
+        unsafe { self.child_ptr(i).as_ref() }.map(|child| &child.val)
+    }
+
+    /// Panics if `i` isn't less than `N`.
+    pub fn child_mut(&mut self, i: usize) -> Option<&mut T> {
+        unsafe { self.child_ptr(i).as_mut() }.map(|child| &mut child.val)
+    }
+
+    /// How many children the node has, not counting empty slots.
+    pub fn arity(&self) -> usize {
+        self.iter.entered_arity(self.node)
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.arity() == 0
+    }
+
+    /// Puts `subtree` into child slot `i`, returning whatever was there. The
+    /// slot keeps its tag, and the traversal goes on into `subtree` once this
+    /// is let go, so children can be filled in as they're reached.
+    ///
+    /// Panics if `i` isn't less than `N`.
+    pub fn graft(&mut self, i: usize, subtree: Tree<T, N>) -> Tree<T, N> {
+        Tree::from_raw(self.iter.graft_entered(self.node, i, subtree.into_raw()))
+    }
+
+    /// Carries on past this node without visiting anything below it.
+    pub fn skip_children(self) {
+        self.iter.skip_children(self.node);
+    }
+}
+
+/// A read-only view of a node and, through its children, everything below it.
+pub struct NodeRef<'tree, T, const N: usize> {
+    tree: &'tree Tree<T, N>,
+    node: *const Node<T, N>,
+}
+
+impl<T, const N: usize> Clone for NodeRef<'_, T, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, const N: usize> Copy for NodeRef<'_, T, N> {}
+
+impl<'tree, T, const N: usize> NodeRef<'tree, T, N> {
+    pub fn value(&self) -> &'tree T {
+        // SAFETY: The tree is borrowed so the node can't be freed
+        unsafe { &(*self.node).val }
+    }
+
+    /// Panics if `i` isn't less than `N`, or if the tree is partway through a
+    /// [`Tree::dfs_iter`].
+    pub fn child(&self, i: usize) -> Option<Self> {
+        assert!(!self.tree.is_traversing(), "tree is being iterated over");
+        // SAFETY: No traversal is in progress so the links are all in place
+        let child = unsafe { (*self.node).children[i].as_untagged() };
+        (!child.is_null()).then_some(Self {
+            tree: self.tree,
+            node: child,
+        })
+    }
+
+    /// See [`Node::child_tag`].
+    pub fn child_tag(&self, i: usize) -> usize {
+        // SAFETY: Tags stay on their slots even partway through a traversal
+        unsafe { (*self.node).child_tag(i) }
+    }
+
+    /// How many children the node has, not counting empty slots.
+    pub fn arity(&self) -> usize {
+        (0..N).filter(|&i| self.child(i).is_some()).count()
+    }
+}
+
+pub struct DfsIter<'tree, T, const N: usize> {
+    iter: NodeIter<'tree, T, N, 0>,
+    tree: &'tree Tree<T, N>,
+    started: bool,
+}
+
+impl<'tree, T, const N: usize> Iterator for DfsIter<'tree, T, N> {
+    type Item = &'tree T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            if self.tree.is_traversing() {
+                // The other traversal owns the reversed links, so don't let our
+                // drop try to put them back
+                self.iter.cur = ptr::null_mut();
+                panic!("tree is already being iterated over");
+            }
+        }
+        let node = self.iter.next()?;
+        Some(unsafe { &(*node).val })
+    }
+}
+
+/// Yields the values of a tree in preorder, freeing each node as soon as its
+/// subtree is done.
+pub struct IntoIter<T, const N: usize> {
+    iter: NodeIter<'static, T, N, 0>,
+}
+
+// SAFETY: The rest of the tree is owned by the iterator
+unsafe impl<T: Send, const N: usize> Send for IntoIter<T, N> {}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, visited) = self.iter.step()?;
+            // SAFETY: Each node is owned by us and only read from once, on the
+            // way in
+            let val = (visited == 0).then(|| unsafe { ptr::read(&(*node).val) });
+            if visited == N {
+                // Its value has already been moved out. The dangling pointer
+                // left in `prev` is only ever copied back into the parent.
+                let _ = unsafe { Box::from_raw(node as *mut ManuallyDrop<Node<T, N>>) };
+            }
+            if val.is_some() {
+                return val;
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> Drop for IntoIter<T, N> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+    }
+}
+
+impl<T, const N: usize> IntoIterator for Tree<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iter: NodeIter::new(self.into_raw()),
+        }
+    }
+}
+
+impl<'tree, T, const N: usize> IntoIterator for &'tree Tree<T, N> {
+    type Item = &'tree T;
+    type IntoIter = DfsIter<'tree, T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.dfs_iter()
+    }
+}
+
+impl<'tree, T, const N: usize> IntoIterator for &'tree mut Tree<T, N> {
+    type Item = &'tree mut T;
+    type IntoIter = DfsIterMut<'tree, T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.dfs_iter_mut()
+    }
+}
+
+// Once a traversal has stepped out of the root it has no current node, and
+// stepping with no current node does nothing. So every iterator built on one
+// keeps returning `None`.
+impl<T, const N: usize, const RETURN_ON_VISIT: usize, O: TraversalObserver> FusedIterator
+    for NodeIter<'_, T, N, RETURN_ON_VISIT, O>
+{
+}
+impl<T, const N: usize, O: TraversalObserver> FusedIterator for DfsIterMut<'_, T, N, O> {}
+impl<T, const N: usize> FusedIterator for DfsIter<'_, T, N> {}
+impl<T, const N: usize> FusedIterator for IntoIter<T, N> {}
+impl<'tree, T: 'tree, const N: usize> FusedIterator for RawWalk<'tree, T, N> {}
+impl<'tree, T: 'tree, const N: usize> FusedIterator for DfsBoundedMut<'tree, T, N> {}
+impl<'tree, T: 'tree, const N: usize> FusedIterator for DfsReorderedMut<'tree, T, N> {}
+impl<'tree, T: 'tree, const N: usize> FusedIterator for DfsVisitsMut<'tree, T, N> {}
+impl<'tree, T: 'tree, const N: usize, F: FnMut(&T, &T) -> Ordering> FusedIterator
+    for DfsBestFirstMut<'tree, T, N, F>
+{
+}
+// A pass that doesn't reach its level is the last one, and an exhausted pass
+// stays exhausted.
+impl<'tree, T: 'tree, const N: usize> FusedIterator for LevelOrderMut<'tree, T, N> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::observer::{Counts, MaxDepth};
+    use std::io::Write as _;
+
+    fn assert_dfs_valid<T: Clone + Debug + PartialEq, const N: usize>(
+        expected: impl IntoIterator<Item = T>,
+        mut tree: Tree<T, N>,
+    ) {
+        let expected: Vec<T> = expected.into_iter().collect();
+        let actual: Vec<T> = tree.dfs_iter_mut().map(|v| v.clone()).collect();
+        assert_eq!(expected, actual);
+    }
+
+    fn null<T, const N: usize>() -> TaggedPtr<Node<T, N>> {
+        TaggedPtr::from_untagged(ptr::null_mut())
+    }
+
+    fn node<T, const N: usize>(
+        val: T,
+        children: [TaggedPtr<Node<T, N>>; N],
+    ) -> TaggedPtr<Node<T, N>> {
+        let node = Node { val, children };
+        TaggedPtr::from_untagged(Box::into_raw(Box::new(node)))
+    }
+
+    fn leaf<T, const N: usize>(val: T) -> TaggedPtr<Node<T, N>> {
+        node(val, [null(); N])
+    }
+
+    fn tree<T, const N: usize>(root: TaggedPtr<Node<T, N>>) -> Tree<T, N> {
+        Tree::from_raw(root.as_untagged())
+    }
+
+    #[test]
+    fn empty() {
+        assert_dfs_valid::<i32, 2>([], tree(null()));
+        assert!(Tree::<i32, 2>::default().is_empty());
+        assert!(!tree::<_, 2>(leaf(0)).is_empty());
+    }
+
+    #[test]
+    fn one() {
+        assert_dfs_valid::<_, 2>([0], tree(leaf(0)));
+    }
+
+    #[test]
+    fn two() {
+        assert_dfs_valid([0, 1], tree(node(0, [leaf(1), null()])));
+    }
+
+    #[test]
+    fn basic() {
+        assert_dfs_valid(
+            0..=5,
+            tree(node(
+                0,
+                [node(1, [leaf(2), null()]), node(3, [leaf(4), leaf(5)])],
+            )),
+        );
+    }
+
+    #[test]
+    fn root() {
+        let mut tree = tree(node(0, [node(1, [leaf(2), null()]), leaf(3)]));
+        *tree.root_mut().unwrap() = 4;
+        assert_eq!(Some(&4), tree.root());
+        let root = tree.root_node().unwrap();
+        let left = root.child(0).unwrap();
+        assert_eq!(
+            (&1, Some(&2)),
+            (left.value(), left.child(0).map(|n| n.value()))
+        );
+        assert!(left.child(1).is_none());
+        assert_eq!(Some(&3), root.child(1).map(|n| n.value()));
+        assert_eq!(None, Tree::<i32, 2>::empty().root());
+    }
+
+    #[test]
+    fn paths() {
+        let tree = tree(node(0, [node(1, [null(), leaf(2)]), null()]));
+        assert_eq!(Some(&2), tree.node_at([0, 1]).map(|n| n.value()));
+        assert!(tree.node_at([1]).is_none());
+        assert_eq!(Some(1), tree.arity_at([]));
+        assert_eq!(Some(0), tree.arity_at([0, 1]));
+        assert_eq!(None, tree.arity_at([0, 0]));
+        assert!(tree.has_children([0]));
+        assert!(!tree.has_children([0, 1]));
+        assert!(!tree.has_children([1, 0]));
+        assert!(tree.node_at([2]).is_none());
+    }
+
+    #[test]
+    fn index() {
+        let mut tree = tree(node(0, [node(1, [null(), leaf(2)]), null()]));
+        tree[[0, 1]] += 5;
+        assert_eq!(7, tree[&[0, 1]]);
+        assert_eq!(0, tree[[]]);
+        assert_eq!(Some(&1), tree.get([0]));
+        assert_eq!(None, tree.get([1]));
+        assert_eq!(None, tree.get([0, 7]));
+        let path = TreePath::parse("0/1").unwrap();
+        assert_eq!(7, tree[&path]);
+        assert_eq!(Some(0), tree.arity_at(path));
+    }
+
+    #[test]
+    fn get_or_insert_child() {
+        let mut tree = Tree::<_, 2>::new(Some(Node::alloc(0, [None, None])));
+        *tree.get_or_insert_child([], 1, 1) += 10;
+        assert_eq!(11, *tree.get_or_insert_child([], 1, 2));
+        tree.get_or_insert_child([1], 0, 3);
+        assert_dfs_valid([0, 11, 3], tree);
+    }
+
+    #[test]
+    fn split_off_at_path() {
+        let sample = || tree(node(0, [node(1, [null(), leaf(2)]), leaf(3)]));
+        let (rest, subtree) = sample().split_off_at_path([0]);
+        assert_dfs_valid([0, 3], rest);
+        assert_dfs_valid([1, 2], subtree);
+
+        let (rest, subtree) = sample().split_off_at_path([]);
+        assert!(rest.is_empty());
+        assert_dfs_valid([0, 1, 2, 3], subtree);
+
+        let (rest, subtree) = sample().split_off_at_path([0, 0]);
+        assert_dfs_valid([0, 1, 2, 3], rest);
+        assert!(subtree.is_empty());
+    }
+
+    #[test]
+    fn folds() {
+        let sample = tree(node(3, [node(1, [null(), leaf(4)]), leaf(1)]));
+        assert_eq!(9, sample.sum::<i32>());
+        assert_eq!((Some(&1), Some(&4)), (sample.min(), sample.max()));
+        assert_eq!(2, sample.count_if(|&v| v == 1));
+        assert_eq!(None, Tree::<i32, 2>::empty().max());
+        // Ties go the same way as the iterator adapters
+        assert!(ptr::eq(sample.min().unwrap(), sample.get([0]).unwrap()));
+        let twin_max = tree::<_, 2>(node(4, [leaf(4), null()]));
+        assert!(ptr::eq(twin_max.max().unwrap(), twin_max.get([0]).unwrap()));
+
+        // Bailing out of a fold leaves the tree whole
+        let result = std::panic::catch_unwind(|| {
+            sample.count_if(|&v| {
+                assert!(v != 4);
+                true
+            })
+        });
+        assert!(result.is_err());
+        assert_dfs_valid([3, 1, 4, 1], sample);
+    }
+
+    #[test]
+    fn number_preorder() {
+        let mut sample = tree(node((7, 0), [node((8, 0), [leaf((9, 0)), null()]), null()]));
+        assert_eq!(3, sample.number_preorder(|(_, i), n| *i = n));
+        assert_eq!(Some(&(9, 2)), sample.get([0, 0]));
+        assert_eq!(
+            0,
+            Tree::<u8, 2>::empty().number_preorder(|_, _| unreachable!())
+        );
+    }
+
+    #[test]
+    fn sort_children_by_key() {
+        let mut sample = tree(node(
+            5,
+            [node(9, [leaf(2), null(), leaf(1)]), null(), leaf(3)],
+        ));
+        sample.sort_children_by_key(|&v| v);
+        assert_dfs_valid([5, 3, 9, 1, 2], sample.clone());
+        assert_eq!(None, sample.get([2]));
+        sample.sort_children_by_key(|&v| std::cmp::Reverse(v));
+        assert_dfs_valid([5, 9, 2, 1, 3], sample);
+        // Ties keep their order
+        let mut ties = tree(node(0, [leaf(4), leaf(3), leaf(2), leaf(1)]));
+        ties.sort_children_by_key(|&v| v % 2);
+        assert_dfs_valid([0, 4, 2, 3, 1], ties);
+    }
+
+    #[test]
+    fn find_with_path() {
+        let mut tree = tree(node(
+            0,
+            [node(1, [null(), leaf(2)]), node(3, [leaf(4), null()])],
+        ));
+        let mut path = TreePath::root();
+        assert_eq!(
+            Some(&mut 2),
+            tree.find_with_path(&mut path, |&v| v % 2 == 0 && v > 0)
+        );
+        assert_eq!(&[0, 1], &*path);
+        assert_eq!(Some(&mut 4), tree.find_with_path(&mut path, |&v| v > 3));
+        assert_eq!(&[1, 0], &*path);
+        assert_eq!(Some(&mut 0), tree.find_with_path(&mut path, |_| true));
+        assert!(path.is_empty());
+        assert_eq!(None, tree.find_with_path(&mut path, |&v| v > 4));
+        assert!(path.is_empty());
+        assert_dfs_valid(0..=4, tree);
+    }
+
+    #[test]
+    fn replace_root_tree() {
+        let mut tree = tree(node(0, [leaf(1), null()]));
+        let old = tree.replace_root(Tree::new(Some(Node::alloc(2, [None, None]))));
+        assert_dfs_valid([0, 1], old);
+        assert_dfs_valid([2], tree);
+    }
+
+    #[test]
+    fn append_child() {
+        let mut parent = tree(node(0, [null(), leaf(1)]));
+        assert!(parent.append_child([], tree(leaf(2))).is_ok());
+        let full = parent.append_child([], tree(leaf(3)));
+        assert_dfs_valid([3], full.unwrap_err());
+        assert!(parent.append_child([0], Tree::empty()).is_ok());
+        assert!(parent.append_child([1], tree(leaf(4))).is_ok());
+        assert_dfs_valid([0, 2, 1, 4], parent);
+    }
+
+    #[test]
+    fn try_into_arity() {
+        let sample = || {
+            tree(node(
+                "a".to_owned(),
+                [
+                    node("b".to_owned(), [null(), leaf("c".to_owned())]),
+                    leaf("d".to_owned()),
+                ],
+            ))
+        };
+        let wide = sample().try_into_arity::<4>().unwrap();
+        assert_eq!(Some("c"), wide.get([0, 1]).map(String::as_str));
+        assert_eq!(Some(2), wide.arity_at([]));
+        assert_dfs_valid(["a", "b", "c", "d"].map(String::from), wide);
+
+        let narrow = sample().try_into_arity::<1>().unwrap_err();
+        assert_dfs_valid(["a", "b", "c", "d"].map(String::from), narrow);
+        let list = tree(node(0, [node(1, [leaf(2), null()]), null()]));
+        assert_dfs_valid([0, 1, 2], list.try_into_arity::<1>().unwrap());
+        assert!(Tree::<i32, 2>::empty()
+            .try_into_arity::<3>()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    #[should_panic = "no node at path [1, 0]"]
+    fn index_missing() {
+        let tree = tree(node(0, [leaf(1), null()]));
+        let _ = tree[[1, 0]];
+    }
+
+    #[test]
+    fn display() {
+        let tree = tree(node(0, [node(1, [null(), leaf(2)]), leaf(3)]));
+        assert_eq!("0\n  1\n    2\n  3\n", tree.to_string());
+        assert_eq!("", Tree::<i32, 2>::empty().to_string());
+    }
+
+    #[test]
+    fn debug() {
+        let sample = tree(node(0, [node(1, [null(), leaf(2)]), leaf(3)]));
+        assert_eq!("Tree<_, 2> { 0 [1 [_, 2], 3] }", format!("{sample:?}"));
+        assert_eq!(
+            "Tree<_, 2> { 0 [1 [..], 3] }",
+            format!("{:?}", sample.debug_limited(1, usize::MAX))
+        );
+        assert_eq!(
+            "Tree<_, 2> { 0 [1 [_, ..]] }",
+            format!("{:?}", sample.debug_limited(usize::MAX, 2))
+        );
+        assert_eq!("Tree<_, 2> { }", format!("{:?}", Tree::<i32, 2>::empty()));
+        assert_eq!("Tree<_, 0> { 0 }", format!("{:?}", tree::<_, 0>(leaf(0))));
+
+        // Deep enough to overflow the stack if this recursed
+        let mut list = leaf(0);
+        for i in 1..100_000 {
+            list = node(i, [list]);
+        }
+        let list = tree(list);
+        let closing = "]".repeat(99_999);
+        assert!(format!("{list:?}").ends_with(&format!("1 [0{closing} }}")));
+    }
+
+    #[test]
+    fn debug_dump() {
+        let mut sample = tree(node(0, [leaf(1), node(2, [leaf(3), null()])]));
+        // Nodes 1 and 3 each have two empty slots, and 2 has one
+        let clean = format!("{:?}", sample.debug_dump());
+        let lines: Vec<&str> = clean.lines().collect();
+        assert_eq!(9, lines.len());
+        assert!(lines[0].starts_with("<0x") && lines[0].ends_with("|0|0> 0"));
+        assert!(lines[4].starts_with("  <0x") && lines[4].ends_with("|0|0> 2"));
+        assert_eq!("    <0x0|0|0>", lines[8]);
+        // SAFETY: Nothing's traversing the tree
+        unsafe { (*sample.root.cast_mut()).set_child_tag(1, 1) };
+        let tagged = format!("{:?}", sample.debug_dump());
+        assert!(tagged.lines().nth(4).unwrap().ends_with("|0|1> 2"));
+        unsafe { (*sample.root.cast_mut()).set_child_tag(1, 0) };
+        assert!(!clean.contains("|1|"));
+
+        // Node 3 is next, and hasn't been entered yet
+        let mut iter = sample.dfs_iter_mut();
+        iter.by_ref().take(3).for_each(drop);
+        let dump = format!("{:?}", iter.debug_dump());
+        assert!(dump.lines().next().unwrap().ends_with("|0|0> 3"));
+        assert_eq!(3, dump.lines().count());
+        drop(iter);
+        assert_eq!(clean, format!("{:?}", sample.debug_dump()));
+
+        // Meanwhile the root only leads to the seen link up to nothing and the
+        // seen (finished) node 1
+        let mut iter = NodeIter::<_, 2, 0>::new(sample.root);
+        iter.by_ref().take(3).for_each(drop);
+        let dump = format!("{:?}", sample.debug_dump());
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(3, lines.len());
+        assert_eq!("  <0x0|1|0>", lines[1]);
+        assert!(lines[2].ends_with("|1|0>"));
+    }
+
+    #[test]
+    fn try_from_parts() {
+        let parts = vec![
+            (0, [Some(2), None]),
+            (2, [None, None]),
+            (1, [None, Some(1)]),
+        ];
+        let sample = Tree::try_from_parts(parts).unwrap();
+        assert_eq!(Some(&2), sample.get([0, 1]));
+        assert_dfs_valid([0, 1, 2], sample);
+        assert!(Tree::<u8, 2>::try_from_parts(vec![]).unwrap().is_empty());
+
+        let check = |parts| Tree::<u8, 2>::try_from_parts(parts).unwrap_err();
+        assert_eq!(
+            StructureError::OutOfBounds { node: 0, child: 3 },
+            check(vec![(0, [Some(3), None])])
+        );
+        // Shared child, cycle back to the root and a cycle off on its own
+        let shared = vec![(0, [Some(1), Some(1)]), (1, [None, None])];
+        assert_eq!(StructureError::ReachedTwice { node: 1 }, check(shared));
+        assert_eq!(
+            StructureError::ReachedTwice { node: 0 },
+            check(vec![(0, [Some(0), None])])
+        );
+        let island = vec![
+            (0, [None, None]),
+            (1, [Some(2), None]),
+            (2, [Some(1), None]),
+        ];
+        assert_eq!(StructureError::Unreachable { node: 1 }, check(island));
+    }
+
+    #[test]
+    fn arbitrary_with() {
+        // Pairs of bytes as u16s, with nothing where the pair starts with 0
+        let decode = |data: &mut &[u8]| {
+            let (val, rest) = data.split_at_checked(2)?;
+            *data = rest;
+            (val[0] != 0).then(|| u16::from_be_bytes([val[0], val[1]]))
+        };
+        let data = [1, 2, 2, 0, 3, 4, 5, 6];
+        let (sample, expected) = Tree::<_, 2>::arbitrary_with(&data, decode);
+        assert_eq!(vec![0x0102, 0x0304, 0x0506], expected);
+        assert_dfs_valid(expected, sample);
+        let (sample, expected) = Tree::<_, 2>::arbitrary_with(&[0, 1, 2, 0], decode);
+        assert!(sample.is_empty() && expected.is_empty());
+    }
+
+    #[test]
+    fn encode_decode() {
+        let encode = |val: &u8, writer: &mut Vec<u8>| writer.write_all(&[*val]);
+        let decode = |reader: &mut &[u8]| {
+            let mut val = [0];
+            reader.read_exact(&mut val)?;
+            Ok(val[0])
+        };
+        let sample = tree(node(0, [node(1, [null(), leaf(2)]), leaf(3)]));
+        let mut bytes = Vec::new();
+        sample.encode_to(&mut bytes, encode).unwrap();
+        assert_eq!(vec![1, 0, 1, 1, 0, 1, 2, 0, 0, 1, 3, 0, 0], bytes);
+        let decoded = Tree::<u8, 2>::decode_from(&mut &bytes[..], decode).unwrap();
+        assert_eq!(format!("{sample:?}"), format!("{decoded:?}"));
+
+        let mut bytes = Vec::new();
+        Tree::<u8, 2>::empty()
+            .encode_to(&mut bytes, encode)
+            .unwrap();
+        assert_eq!(vec![0], bytes);
+        assert!(Tree::<u8, 2>::decode_from(&mut &bytes[..], decode)
+            .unwrap()
+            .is_empty());
+
+        // Cut short and corrupted input, with the partial tree cleaned up
+        let err = Tree::<u8, 2>::decode_from(&mut &[1, 0, 1, 1][..], decode).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+        let err = Tree::<u8, 2>::decode_from(&mut &[1, 0, 2][..], decode).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn arbitrary_deep() {
+        // Every fence is 0, so everything goes to the last child
+        let data: Vec<u8> = (0..20_000).flat_map(|_| [1, 0, 0]).collect();
+        let (sample, values) = Tree::<_, 2>::arbitrary(&data);
+        assert_eq!(20_000, values.len());
+        assert_eq!(20_000, sample.dfs_iter().count());
+    }
+
+    #[test]
+    fn from_fn() {
+        let perfect = Tree::<_, 2>::from_fn(2, |path| Some(path.to_vec()));
+        let expected: [&[usize]; 7] = [&[], &[0], &[0, 0], &[0, 1], &[1], &[1, 0], &[1, 1]];
+        assert_dfs_valid(expected.map(|p| p.to_vec()), perfect);
+
+        // A spine down the first children with a tooth in each second slot
+        let comb = Tree::<_, 3>::from_fn(3, |path| {
+            let spine = path.iter().rev().skip(1).all(|&i| i == 0);
+            (spine && path.last() != Some(&2)).then_some(path.len())
+        });
+        assert_dfs_valid([0, 1, 2, 3, 3, 2, 1], comb);
+        assert!(Tree::<i32, 2>::from_fn(5, |_| None).is_empty());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn cycle() {
+        let a = node(0, [null(), null()]);
+        let b = node(1, [a, null()]);
+        unsafe { (*a.as_untagged()).children[1] = b };
+        let mut tree = tree(a);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            tree.dfs_iter_mut().count();
+        }));
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.ends_with("was reached twice, so this isn't a tree"));
+        // The links were put back, so we can cut the cycle and free it
+        unsafe { (*b.as_untagged()).children[0] = null() };
+        assert_dfs_valid([0, 1], tree);
+    }
+
+    #[test]
+    fn observer() {
+        let sample = || tree(node(0, [node(1, [leaf(2), null()]), leaf(3)]));
+        let mut sample_tree = sample();
+        let mut counts = Counts::default();
+        assert_eq!(4, sample_tree.dfs_iter_mut_with(&mut counts).count());
+        assert_eq!((4, 4, 0), (counts.entered, counts.left, counts.repaired));
+
+        let mut counts = Counts::default();
+        let mut iter = sample_tree.dfs_iter_mut_with(&mut counts);
+        iter.nth(2);
+        drop(iter);
+        // Stopped inside 2, so it and both ancestors needed putting back
+        assert_eq!((3, 0, 3), (counts.entered, counts.left, counts.repaired));
+
+        let mut depth = MaxDepth::default();
+        sample_tree.dfs_iter_mut_with(&mut depth).for_each(drop);
+        assert_eq!(3, depth.max_depth_seen());
+        let mut depth = MaxDepth::default();
+        tree(leaf::<_, 2>(0))
+            .dfs_iter_mut_with(&mut depth)
+            .for_each(drop);
+        assert_eq!(1, depth.max_depth_seen());
+
+        let mut counts = Counts::default();
+        sample_tree.drop_with(&mut counts);
+        assert_eq!(4, counts.dropped);
+    }
+
+    #[test]
+    fn drop_order() {
+        use std::{cell::RefCell, rc::Rc};
+
+        struct Recorder(u8, Rc<RefCell<Vec<u8>>>);
+
+        impl Drop for Recorder {
+            fn drop(&mut self) {
+                self.1.borrow_mut().push(self.0);
+            }
+        }
+
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let sample = || {
+            let r = |val| Recorder(val, dropped.clone());
+            tree(node(r(0), [node(r(1), [leaf(r(2)), null()]), leaf(r(3))]))
+        };
+        drop(sample());
+        assert_eq!(vec![2, 1, 3, 0], dropped.take());
+        sample().drop_with(());
+        assert_eq!(vec![2, 1, 3, 0], dropped.take());
+
+        let mut seen = Vec::new();
+        sample().drop_each(|r| seen.push(r.0));
+        assert_eq!(vec![2, 1, 3, 0], seen);
+        assert_eq!(vec![2, 1, 3, 0], dropped.take());
+
+        // Dropping an iterator partway through doesn't change anything
+        let mut partway = sample();
+        partway.dfs_iter_mut().nth(2);
+        drop(partway);
+        assert_eq!(vec![2, 1, 3, 0], dropped.take());
+    }
+
+    #[test]
+    fn covariant() {
+        fn shorten<'a>(tree: Tree<&'static str, 2>) -> Tree<&'a str, 2> {
+            tree
+        }
+        fn shorten_ref<'a, 'b>(tree: &'b Tree<&'static str, 2>) -> &'b Tree<&'a str, 2> {
+            tree
+        }
+        let local = String::from("local");
+        let sample = tree(node("static", [leaf("left"), null()]));
+        assert_eq!(Some(&"left"), shorten_ref(&sample).get([0]));
+        let mut sample = shorten(sample);
+        sample[[0]] = &local;
+        assert_dfs_valid(["static", "local"], sample);
+    }
+
+    #[test]
+    fn postorder_iter_mut() {
+        let post =
+            |mut tree: Tree<i32, 3>| tree.postorder_iter_mut().map(|v| *v).collect::<Vec<_>>();
+        assert!(post(tree(null())).is_empty());
+        assert_eq!(vec![0], post(tree(leaf(0))));
+        assert_eq!(
+            vec![2, 3, 1, 5, 4, 0],
+            post(tree(node(
+                0,
+                [
+                    node(1, [leaf(2), null(), leaf(3)]),
+                    null(),
+                    node(4, [null(), leaf(5), null()])
+                ]
+            )))
+        );
+
+        let mut tree = tree::<_, 2>(node(1, [leaf(2), leaf(3)]));
+        let mut iter = tree.postorder_iter_mut();
+        *iter.next().unwrap() = 20;
+        drop(iter);
+        assert_dfs_valid([1, 20, 3], tree);
+    }
+
+    #[test]
+    fn dfs_iter_mut_at() {
+        let mut tree = tree::<_, 2>(node(
+            3,
+            [node(1, [leaf(0), leaf(2)]), node(5, [leaf(4), null()])],
+        ));
+        let at = |tree: &mut Tree<i32, 2>, visit| {
+            tree.dfs_iter_mut_at(visit).map(|v| *v).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            tree.dfs_iter().copied().collect::<Vec<_>>(),
+            at(&mut tree, 0)
+        );
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], at(&mut tree, 1));
+        assert_eq!(
+            tree.postorder_iter_mut().map(|v| *v).collect::<Vec<_>>(),
+            at(&mut tree, 2)
+        );
+        tree.dfs_iter_mut_at(1).take(3).for_each(|v| *v += 10);
+        assert_dfs_valid([3, 11, 10, 12, 5, 4], tree);
+    }
+
+    #[test]
+    #[should_panic = "visit 3 out of bounds for arity 2"]
+    fn dfs_iter_mut_at_out_of_bounds() {
+        let _ = tree::<_, 2>(leaf(0)).dfs_iter_mut_at(3);
+    }
+
+    #[test]
+    fn raw_walk() {
+        let mut b = Node::alloc(1, [None, None]);
+        b.set_child_tag(1, 1);
+        let mut tree = Tree::new(Some(Node::alloc(0, [Some(b), None])));
+        let mut steps = Vec::new();
+        let mut ids = Vec::new();
+        for visit in tree.raw_walk() {
+            steps.push((*visit.value(), visit.visited(), visit.child_tag(1)));
+            ids.push(visit.id());
+            if visit.visited() == 2 {
+                // SAFETY: Nothing else is looking at the value
+                unsafe { *visit.value_mut() += 10 };
+            }
+        }
+        assert_eq!(
+            vec![
+                (0, 0, 0),
+                (1, 0, 1),
+                (1, 1, 1),
+                (1, 2, 1),
+                (0, 1, 0),
+                (0, 2, 0)
+            ],
+            steps
+        );
+        ids.dedup();
+        assert_eq!(3, ids.len());
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(ids[0], ids[2]);
+        assert_dfs_valid([10, 11], tree);
+    }
+
+    #[test]
+    fn contains_shape_of() {
+        let big = tree::<_, 2>(node(0, [node(1, [leaf(3), leaf(4)]), leaf(2)]));
+        let contains = |small: Tree<i32, 2>| big.contains_shape_of(&small);
+        assert!(contains(tree(null())));
+        assert!(contains(tree(leaf(0))));
+        assert!(contains(tree(node(0, [null(), leaf(2)]))));
+        assert!(contains(tree(node(
+            0,
+            [node(1, [null(), leaf(4)]), null()]
+        ))));
+        assert!(!contains(tree(leaf(1))));
+        assert!(!contains(tree(node(
+            0,
+            [node(1, [leaf(4), null()]), null()]
+        ))));
+        assert!(!contains(tree(node(
+            0,
+            [null(), node(2, [leaf(5), null()])]
+        ))));
+        assert!(big.contains_shape_of(&big));
+        assert!(!tree(null()).contains_shape_of(&big));
+        // Bailing out partway leaves both trees as they were
+        assert_dfs_valid([0, 1, 3, 4, 2], big);
+    }
+
+    #[test]
+    fn dfs_iter_mut_best_first() {
+        let mut tree = tree::<_, 3>(node(
+            5,
+            [leaf(1), null(), node(9, [leaf(2), leaf(8), leaf(8)])],
+        ));
+        let order: Vec<i32> = tree
+            .dfs_iter_mut_best_first(|a: &i32, b| b.cmp(a))
+            .map(|v| *v)
+            .collect();
+        assert_eq!(vec![5, 9, 8, 8, 2, 1], order);
+        // The slots haven't moved
+        assert!(tree.get([1]).is_none());
+        assert_eq!(
+            vec![5, 1, 9, 2, 8, 8],
+            tree.dfs_iter().copied().collect::<Vec<_>>()
+        );
+
+        let order: Vec<i32> = tree
+            .dfs_iter_mut_best_first(|a: &i32, b| a.cmp(b))
+            .map(|v| *v)
+            .collect();
+        assert_eq!(vec![5, 1, 9, 2, 8, 8], order);
+
+        // Stopping partway puts everything back too
+        let mut iter = tree.dfs_iter_mut_best_first(|a: &i32, b| b.cmp(a));
+        assert_eq!(Some(&mut 8), iter.nth(2));
+        drop(iter);
+        assert_dfs_valid([5, 1, 9, 2, 8, 8], tree);
+    }
+
+    #[test]
+    fn dfs_iter_mut_best_first_panics() {
+        let mut big = tree::<_, 3>(node(
+            0,
+            [node(1, [leaf(4), leaf(5), null()]), leaf(2), leaf(3)],
+        ));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            big.dfs_iter_mut_best_first(|a: &i32, b| {
+                assert!(*a < 5 && *b < 5, "too big");
+                b.cmp(a)
+            })
+            .count()
+        }));
+        assert!(result.is_err());
+        assert_dfs_valid([0, 1, 4, 5, 2, 3], big);
+    }
+
+    #[test]
+    fn dfs_iter_mut_rev_children() {
+        let mut tree = tree(node(
+            0,
+            [
+                node(1, [leaf(2), null(), leaf(3)]),
+                null(),
+                node(4, [null(), leaf(5), null()]),
+            ],
+        ));
+        let order: Vec<i32> = tree.dfs_iter_mut_rev_children().map(|v| *v).collect();
+        assert_eq!(vec![0, 4, 5, 1, 3, 2], order);
+        tree.dfs_iter_mut_rev_children()
+            .take(3)
+            .for_each(|v| *v += 10);
+        assert_dfs_valid([10, 1, 2, 3, 14, 15], tree);
+    }
+
+    #[test]
+    fn dfs_iter_mut_shuffled() {
+        let wide = || node(1, [leaf(2), leaf(3), null(), leaf(4), leaf(5)]);
+        let mut tree = tree::<_, 5>(node(0, [wide(), null(), wide(), leaf(6), null()]));
+        let original: Vec<i32> = tree.dfs_iter().copied().collect();
+        let mut orders = Vec::new();
+        for seed in 0..8 {
+            let order: Vec<i32> = tree.dfs_iter_mut_shuffled(seed).map(|v| *v).collect();
+            let mut sorted = order.clone();
+            sorted.sort();
+            let mut expected = original.clone();
+            expected.sort();
+            assert_eq!(expected, sorted);
+            // Still depth first, so each wide subtree comes out in one piece
+            let start = order.iter().position(|&v| v == 1).unwrap();
+            assert!(order[start + 1..start + 5].iter().all(|&v| v > 1 && v < 6));
+            orders.push(order);
+        }
+        orders.dedup();
+        assert!(orders.len() > 1);
+
+        tree.dfs_iter_mut_shuffled(3).take(4).for_each(drop);
+        assert_eq!(original, tree.dfs_iter().copied().collect::<Vec<_>>());
+        let mut copy = tree.clone();
+        for seed in 0..8 {
+            assert_eq!(
+                tree.dfs_iter_mut_shuffled(seed)
+                    .map(|v| *v)
+                    .collect::<Vec<_>>(),
+                copy.dfs_iter_mut_shuffled(seed)
+                    .map(|v| *v)
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(
+            tree.dfs_iter_mut_shuffled(5)
+                .map(|v| *v)
+                .collect::<Vec<_>>(),
+            tree.dfs_iter_mut_shuffled(5)
+                .map(|v| *v)
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn dfs_with_parent_mut() {
+        let mut sums = tree::<_, 2>(node(1, [node(2, [leaf(3), null()]), leaf(4)]));
+        // Each value becomes the sum along its path from the root
+        let mut iter = sums.dfs_with_parent_mut();
+        while let Some((parent, val)) = iter.next() {
+            *val += parent.copied().unwrap_or(0);
+        }
+        drop(iter);
+        assert_dfs_valid([1, 3, 6, 5], sums);
+
+        let mut tree = tree::<_, 0>(leaf(7));
+        let mut iter = tree.dfs_with_parent_mut();
+        assert!(matches!(iter.next(), Some((None, 7))));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn walk_mut() {
+        let mut sexpr = tree::<_, 2>(node(1, [node(2, [null(), leaf(3)]), leaf(4)]));
+        let mut out = String::new();
+        let mut walk = sexpr.walk_mut();
+        while let Some(event) = walk.next() {
+            match event {
+                Event::Enter(val) => write!(out, "({val}").unwrap(),
+                Event::ChildDone(i) => write!(out, " {i}").unwrap(),
+                Event::Exit(val) => {
+                    *val *= 10;
+                    out.push(')');
+                }
+            }
+        }
+        drop(walk);
+        assert_eq!("(1(2 0(3 0 1) 1) 0(4 0 1) 1)", out);
+        assert_dfs_valid([10, 20, 30, 40], sexpr);
+
+        let mut tree = tree::<_, 0>(leaf(5));
+        let mut walk = tree.walk_mut();
+        assert_eq!(Some(Event::Enter(&mut 5)), walk.next());
+        assert_eq!(Some(Event::Exit(&mut 5)), walk.next());
+        assert_eq!(None, walk.next());
+    }
+
+    #[test]
+    fn dfs_visits_mut() {
+        let mut tree = tree::<_, 3>(node(
+            1,
+            [leaf(2), null(), node(3, [null(), null(), leaf(4)])],
+        ));
+        let mut leaves = 0;
+        let mut arities = Vec::new();
+        for visit in tree.dfs_visits_mut() {
+            if visit.is_leaf {
+                leaves += *visit.value;
+                *visit.value = 0;
+            }
+            arities.push(visit.non_null_children);
+        }
+        assert_eq!(6, leaves);
+        assert_eq!(vec![2, 0, 1, 0], arities);
+        assert_eq!(
+            vec![1, 0, 3, 0],
+            tree.dfs_iter().copied().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn dfs_iter_mut_bounded() {
+        let mut tree = tree(node(
+            0,
+            [
+                node(1, [leaf(2), null()]),
+                node(3, [leaf(4), node(5, [leaf(6), null()])]),
+            ],
+        ));
+        let mut iter = tree.dfs_iter_mut_bounded(1);
+        let mut seen = Vec::new();
+        while let Some(val) = iter.next() {
+            seen.push((*val, iter.depth()));
+        }
+        drop(iter);
+        assert_eq!(vec![(0, 0), (1, 1), (3, 1)], seen);
+        let top: Vec<i32> = tree.dfs_iter_mut_bounded(2).map(|v| *v).collect();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], top);
+        assert_eq!(
+            vec![0],
+            tree.dfs_iter_mut_bounded(0).map(|v| *v).collect::<Vec<_>>()
+        );
+        tree.dfs_iter_mut_bounded(2).nth(4);
+        assert_dfs_valid(0..=6, tree);
+    }
+
+    #[test]
+    fn ids_find() {
+        let mut tree = tree(node(
+            0,
+            [
+                node(1, [node(7, [null(), null()]), null()]),
+                node(3, [leaf(7), leaf(8)]),
+            ],
+        ));
+        let mut calls = 0;
+        let (found, depth) = tree
+            .ids_find(
+                |&v| {
+                    calls += 1;
+                    v == 7
+                },
+                5,
+            )
+            .unwrap();
+        assert_eq!((7, 2), (*found, depth));
+        *found = 70;
+        assert_eq!(4, calls);
+        assert!(tree.ids_find(|&v| v == 8, 1).is_none());
+        assert_eq!(Some(1), tree.ids_find(|&v| v % 2 == 1, 9).map(|(_, d)| d));
+        assert!(tree.ids_find(|&v| v == 100, usize::MAX).is_none());
+        assert_dfs_valid([0, 1, 70, 3, 7, 8], tree);
+    }
+
+    /// Level order the usual way, with a queue.
+    fn bfs<T: Clone, const N: usize>(tree: &Tree<T, N>) -> Vec<T> {
+        let mut queue: std::collections::VecDeque<_> = tree.root_node().into_iter().collect();
+        let mut values = Vec::new();
+        while let Some(node) = queue.pop_front() {
+            values.push(node.value().clone());
+            queue.extend((0..N).filter_map(|i| node.child(i)));
+        }
+        values
+    }
+
+    #[test]
+    fn levelorder_iter_mut() {
+        let data: Vec<u8> = (0..200).map(|i| (i * 37 % 11) as u8).collect();
+        for len in [0, 1, 5, 40, 200] {
+            let (mut tree, _) = Tree::<u8, 3>::arbitrary(&data[..len]);
+            let expected = bfs(&tree);
+            let actual: Vec<u8> = tree.levelorder_iter_mut().map(|v| *v).collect();
+            assert_eq!(expected, actual, "{len}");
+        }
+        let mut chain = tree::<_, 2>(node(0, [null(), node(1, [leaf(2), null()])]));
+        assert_eq!(
+            vec![0, 1, 2],
+            chain.levelorder_iter_mut().map(|v| *v).collect::<Vec<_>>()
+        );
+        *chain.levelorder_iter_mut().nth(1).unwrap() = 10;
+        assert_dfs_valid([0, 10, 2], chain);
+        let mut single = tree::<_, 0>(leaf(3));
+        assert_eq!(
+            vec![3],
+            single.levelorder_iter_mut().map(|v| *v).collect::<Vec<_>>()
+        );
+
+        // Every value can be held onto while later levels are found
+        let mut wide = tree::<_, 2>(node(0, [node(1, [leaf(3), null()]), leaf(2)]));
+        let held: Vec<&mut i32> = wide.levelorder_iter_mut().collect();
+        for val in held {
+            *val += 10;
+        }
+        assert_dfs_valid([10, 11, 13, 12], wide);
+    }
+
+    #[test]
+    fn leaves_mut() {
+        let mut parse = tree::<_, 3>(node(
+            0,
+            [leaf(1), node(2, [null(), leaf(3), null()]), leaf(4)],
+        ));
+        let leaves: Vec<i32> = parse.leaves_mut().map(|v| *v).collect();
+        assert_eq!(vec![1, 3, 4], leaves);
+        assert_eq!(
+            vec![7],
+            tree::<_, 3>(leaf(7))
+                .leaves_mut()
+                .map(|v| *v)
+                .collect::<Vec<_>>()
+        );
+        assert!(tree::<i32, 3>(null()).leaves_mut().next().is_none());
+    }
+
+    #[test]
+    fn dfs_nodes_mut() {
+        let mut tree = tree::<_, 2>(node(
+            1,
+            [node(2, [leaf(3), null()]), node(4, [null(), leaf(5)])],
+        ));
+        let mut nodes = tree.dfs_nodes_mut();
+        let mut arities = Vec::new();
+        while let Some(mut node) = nodes.next() {
+            assert_eq!(*node.value() == 0, node.is_leaf());
+            arities.push(node.arity());
+            if *node.value() == 4 {
+                *node.child_mut(1).unwrap() = 0;
+                assert_eq!(None, node.child(0));
+                node.skip_children();
+                continue;
+            }
+            // Pull each leaf's value up into its parent
+            if let Some(leaf) = node.child_mut(0).filter(|v| **v == 3) {
+                *leaf = 0;
+                *node.value_mut() += 3;
+            }
+        }
+        drop(nodes);
+        assert_eq!(vec![2, 1, 0, 1], arities);
+        assert_eq!(
+            vec![1, 5, 0, 4, 0],
+            tree.dfs_iter().copied().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn dfs_nodes_mut_graft() {
+        let mut sample = tree::<_, 2>(node(1, [leaf(2), null()]));
+        let mut nodes = sample.dfs_nodes_mut();
+        let mut seen = Vec::new();
+        while let Some(mut node) = nodes.next() {
+            let val = *node.value();
+            seen.push(val);
+            // Grow every node under 4 two children deep, replacing what's there
+            if val < 4 {
+                let old = node.graft(0, tree(leaf(val * 2)));
+                assert_eq!(val == 1, old.root() == Some(&2));
+                assert!(node.graft(1, tree(leaf(val * 2 + 1))).is_empty());
+                assert_eq!(2, node.arity());
+            }
+        }
+        drop(nodes);
+        assert_eq!(vec![1, 2, 4, 5, 3, 6, 7], seen);
+        assert_dfs_valid([1, 2, 4, 5, 3, 6, 7], sample);
+    }
+
+    #[test]
+    fn dfs_iter_cells() {
+        let mut tree = tree::<_, 2>(node(1, [leaf(2), node(3, [leaf(4), null()])]));
+        // Carry a running total along, emptying each value behind it
+        let mut seen: Vec<&Cell<i32>> = Vec::new();
+        for cell in tree.dfs_iter_cells() {
+            if let Some(prev) = seen.last() {
+                cell.set(cell.get() + prev.get());
+                prev.set(0);
+            }
+            seen.push(cell);
+        }
+        assert_eq!(
+            vec![0, 0, 0, 10],
+            tree.dfs_iter().copied().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn edge_tags() {
+        assert_eq!(2, Node::<u64, 3>::SPARE_TAG_BITS);
+        let mut b = Node::alloc(1, [None, None, None]);
+        b.set_child_tag(1, 3);
+        let mut a = Node::alloc(0, [Some(b), Some(Node::alloc(2, [None, None, None])), None]);
+        a.set_child_tag(0, 1);
+        a.set_child_tag(2, 2);
+        let mut tree: Tree<u64, 3> = Tree::new(Some(a));
+        let tags = |tree: &Tree<u64, 3>| {
+            let root = tree.root_node().unwrap();
+            let b = root.child(0).unwrap();
+            [
+                root.child_tag(0),
+                root.child_tag(1),
+                root.child_tag(2),
+                b.child_tag(1),
+            ]
+        };
+        assert_eq!([1, 0, 2, 3], tags(&tree));
+
+        // Whole, partway and skipping traversals all leave them in place
+        assert_eq!(3, tree.dfs_iter_mut().count());
+        tree.dfs_iter_mut().take(2).for_each(drop);
+        let _ = format!("{:?}", tree.debug_limited(0, usize::MAX));
+        assert_eq!([1, 0, 2, 3], tags(&tree));
+        // SAFETY: Nothing's traversing the tree
+        let root = unsafe { &*tree.root };
+        assert!(format!("{:?}", root.children[2]).ends_with("|0|2>"));
+        let by_best = tree.dfs_iter_mut_best_first(|a: &u64, b| b.cmp(a)).count();
+        assert_eq!(3, by_best);
+        assert_eq!([1, 0, 2, 3], tags(&tree));
+
+        // Clones copy them, even onto nodes that are being reused
+        let mut copy = tree.clone();
+        assert_eq!([1, 0, 2, 3], tags(&copy));
+        // SAFETY: Nothing's traversing the copy
+        unsafe { (*copy.root.cast_mut()).set_child_tag(1, 2) };
+        copy.clone_from(&tree);
+        assert_eq!([1, 0, 2, 3], tags(&copy));
+    }
+
+    #[test]
+    fn edge_tags_stay_with_slots() {
+        let leaf = |val| Some(Node::alloc(val, [None, None, None]));
+        let mut root = Node::alloc(0, [leaf(2), leaf(1), None]);
+        root.set_child_tag(0, 1);
+        root.set_child_tag(2, 2);
+        let mut tree: Tree<u64, 3> = Tree::new(Some(root));
+        let tags = |tree: &Tree<u64, 3>| {
+            let root = tree.root_node().unwrap();
+            [root.child_tag(0), root.child_tag(1), root.child_tag(2)]
+        };
+
+        tree.sort_children_by_key(|v| *v);
+        assert_eq!(Some(&1), tree.get([0]));
+        assert_eq!([1, 0, 2], tags(&tree));
+
+        let detached = tree.detach([0]);
+        assert_eq!(Some(&1), detached.root());
+        assert_eq!([1, 0, 2], tags(&tree));
+        assert!(tree.append_child([], detached).is_ok());
+        assert_eq!(Some(&1), tree.get([0]));
+        assert_eq!(4, *tree.get_or_insert_child([], 2, 4));
+        assert_eq!([1, 0, 2], tags(&tree));
+
+        let wider = tree.try_into_arity::<4>().ok().unwrap();
+        let root = wider.root_node().unwrap();
+        let tags: Vec<usize> = (0..4).map(|i| root.child_tag(i)).collect();
+        assert_eq!(vec![1, 0, 2, 0], tags);
+    }
+
+    #[test]
+    fn send() {
+        let mut sample = tree(node(0, [node(1, [leaf(2), null()]), leaf(3)]));
+        sample = std::thread::spawn(move || {
+            sample[[1]] = 4;
+            sample
+        })
+        .join()
+        .unwrap();
+
+        let mut iter = sample.dfs_iter_mut();
+        iter.next();
+        let rest =
+            std::thread::scope(|s| s.spawn(move || iter.map(|v| *v).collect::<Vec<_>>()).join());
+        assert_eq!(vec![1, 2, 4], rest.unwrap());
+
+        let mut iter = sample.into_iter();
+        iter.next();
+        let rest = std::thread::spawn(move || iter.collect::<Vec<_>>()).join();
+        assert_eq!(vec![1, 2, 4], rest.unwrap());
+    }
+
+    #[test]
+    fn arity_zero() {
+        let mut single = tree(node(5, []));
+        assert_eq!("Tree<_, 0> { 5 }", format!("{single:?}"));
+        assert_eq!("5\n", single.to_string());
+        assert_eq!(Some(&5), single.get([]));
+        assert_eq!(None, single.get([0]));
+        assert_eq!(Some(0), single.arity_at([]));
+        assert!(format!("{:?}", single.debug_dump()).ends_with("|0> 5\n"));
+        // With no links to reverse, iterators can't get in each other's way
+        let mut outer = single.dfs_iter();
+        assert_eq!(Some(&5), single.dfs_iter().next());
+        assert_eq!(Some(&5), outer.next());
+        drop(outer);
+        single = single.clone();
+        single.sort_children_by_key(|v| *v);
+        assert_dfs_valid([5], single.clone());
+
+        let mut bytes = Vec::new();
+        single
+            .encode_to(&mut bytes, |v, w| w.write_all(&[*v]))
+            .unwrap();
+        assert_eq!(vec![1, 5], bytes);
+        let decoded = Tree::<u8, 0>::decode_from(&mut &bytes[..], |r| {
+            let mut v = [0];
+            r.read_exact(&mut v)?;
+            Ok(v[0])
+        });
+        assert_dfs_valid([5], decoded.unwrap());
+
+        assert_dfs_valid([1], Tree::<_, 0>::from_fn(3, |path| Some(path.len() + 1)));
+        let (single, values) = Tree::<u8, 0>::arbitrary(&[7, 8, 9]);
+        assert_eq!(vec![7], values);
+        assert_dfs_valid([7], single);
+        let single = Tree::<_, 0>::try_from_parts(vec![(1, [])]).unwrap();
+        assert_eq!(vec![1], single.into_iter().collect::<Vec<_>>());
+        let widened = tree(node(5, [])).try_into_arity::<2>().ok().unwrap();
+        assert_dfs_valid([5], widened.try_into_arity::<0>().ok().unwrap());
+    }
+
+    #[test]
+    fn arity_one() {
+        let mut list = tree(node(0, [node(1, [leaf(2)])]));
+        assert_eq!("Tree<_, 1> { 0 [1 [2]] }", format!("{list:?}"));
+        assert_eq!(Some(&2), list.get([0, 0]));
+        assert_eq!(None, list.get([1]));
+        *list.get_or_insert_child([0, 0], 0, 3) += 10;
+        assert_dfs_valid([0, 1, 2, 13], list.clone());
+        let tail = list.detach([0, 0]);
+        assert_dfs_valid([2, 13], tail);
+        let mut copy = Tree::empty();
+        list.clone_into(&mut copy);
+        assert_dfs_valid([0, 1], copy);
+
+        let mut bytes = Vec::new();
+        list.encode_to(&mut bytes, |v, w| w.write_all(&[*v]))
+            .unwrap();
+        assert_eq!(vec![1, 0, 1, 1, 0], bytes);
+
+        assert_dfs_valid([0, 1, 2], Tree::<_, 1>::from_fn(2, |path| Some(path.len())));
+        let (list, values) = Tree::<u8, 1>::arbitrary(&[7, 8, 9]);
+        assert_eq!(vec![7, 8, 9], values);
+        assert_dfs_valid([7, 8, 9], list);
+        let list = Tree::<_, 1>::try_from_parts(vec![(0, [Some(1)]), (1, [None])]);
+        assert_eq!(vec![0, 1], list.unwrap().into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn nochildren() {
+        assert_dfs_valid(["hi"], tree(node("hi", [])));
+    }
+
+    #[test]
+    fn linked_list() {
+        let list = node(0, [node(1, [node(2, [leaf(3)])])]);
+        assert_dfs_valid(0..=3, tree(list));
+    }
+
+    #[test]
+    fn iter_fixes_tree() {
+        let mut tree = tree(node(
+            0,
+            [node(1, [leaf(2), null()]), node(3, [leaf(4), leaf(5)])],
+        ));
+        let mut iter = tree.dfs_iter_mut();
+        assert_eq!(Some(&mut 0), iter.next());
+        assert_eq!(Some(&mut 1), iter.next());
+        assert_eq!(Some(&mut 2), iter.next());
+        drop(iter);
+        assert_dfs_valid(0..=5, tree);
+    }
+
+    #[test]
+    fn visit_mut() {
+        let mut tree = tree(node(
+            0,
+            [node(1, [leaf(2), null()]), node(3, [leaf(4), leaf(5)])],
+        ));
+        let mut seen = Vec::new();
+        let flow = tree.visit_mut(|val| {
+            seen.push(*val);
+            match *val {
+                1 => ControlFlow::Continue(VisitAction::SkipChildren),
+                4 => ControlFlow::Break(*val * 10),
+                _ => ControlFlow::Continue(VisitAction::Continue),
+            }
+        });
+        assert_eq!(ControlFlow::Break(40), flow);
+        assert_eq!(vec![0, 1, 3, 4], seen);
+        assert_dfs_valid(0..=5, tree.clone());
+
+        let flow: ControlFlow<()> = tree.visit_mut(|val| {
+            *val += 1;
+            ControlFlow::Continue(VisitAction::Continue)
+        });
+        assert_eq!(ControlFlow::Continue(()), flow);
+        assert_dfs_valid(1..=6, tree);
+    }
+
+    #[test]
+    fn fused() {
+        fn assert_fused<I: FusedIterator>(mut iter: I, len: usize) {
+            for _ in 0..len {
+                assert!(iter.next().is_some());
+            }
+            for _ in 0..3 {
+                assert!(iter.next().is_none());
+            }
+        }
+        let sample = || tree::<_, 2>(node(0, [node(1, [leaf(2), null()]), leaf(3)]));
+        let mut tree = sample();
+        assert_fused(tree.dfs_iter(), 4);
+        assert_fused(tree.dfs_iter_mut(), 4);
+        assert_fused(tree.raw_walk(), 12);
+        assert_fused(tree.dfs_iter_mut_bounded(1), 3);
+        assert_fused(tree.levelorder_iter_mut(), 4);
+        assert_fused(tree.dfs_iter_mut_shuffled(1), 4);
+        assert_fused(tree.dfs_visits_mut(), 4);
+        assert_fused(tree.dfs_iter_mut_best_first(|a: &i32, b| a.cmp(b)), 4);
+        assert_fused(sample().into_iter(), 4);
+        assert_fused(Tree::<i32, 2>::empty().into_iter(), 0);
+        assert_dfs_valid(0..=3, tree);
+    }
+
+    #[test]
+    fn skip_subtree() {
+        let mut tree = tree(node(
+            0,
+            [node(1, [leaf(2), null()]), node(3, [leaf(4), leaf(5)])],
+        ));
+        let mut seen = Vec::new();
+        let mut iter = tree.dfs_iter_mut();
+        while let Some(val) = iter.next() {
+            seen.push(*val);
+            if *val % 2 == 1 {
+                iter.skip_subtree();
+            }
+        }
+        drop(iter);
+        assert_eq!(vec![0, 1, 3], seen);
+        assert_dfs_valid(0..=5, tree);
+    }
+
+    #[test]
+    #[should_panic = "no value was just yielded"]
+    fn skip_subtree_twice() {
+        let mut tree = tree::<_, 2>(node(0, [leaf(1), null()]));
+        let mut iter = tree.dfs_iter_mut();
+        iter.next();
+        iter.skip_subtree();
+        iter.skip_subtree();
+    }
+
+    #[test]
+    fn replace_subtree() {
+        let mut tree = tree(node(
+            0,
+            [node(1, [leaf(2), null()]), node(3, [leaf(4), leaf(5)])],
+        ));
+        let mut iter = tree.dfs_iter_mut();
+        let mut seen = Vec::new();
+        while let Some(v) = iter.next() {
+            seen.push(*v);
+            if *v == 1 {
+                let old = iter.replace(v, Tree::new(Some(Node::alloc(6, [None, None]))));
+                assert_dfs_valid([1, 2], old);
+            }
+        }
+        assert_eq!(vec![0, 1, 3, 4, 5], seen);
+        drop(iter);
+        assert_dfs_valid([0, 6, 3, 4, 5], tree);
+    }
+
+    #[test]
+    fn replace_root() {
+        let mut tree = tree(node(0, [leaf(1), leaf(2)]));
+        let mut iter = tree.dfs_iter_mut();
+        let root = iter.next().unwrap();
+        let old = iter.replace(root, Tree::empty());
+        assert_eq!(None, iter.next());
+        drop(iter);
+        assert_dfs_valid([0, 1, 2], old);
+        assert_dfs_valid::<i32, 2>([], tree);
+    }
+
+    #[test]
+    #[should_panic = "can only replace the value that was just yielded"]
+    fn replace_stale() {
+        let mut tree = tree(node(0, [leaf(1), leaf(2)]));
+        let mut iter = tree.dfs_iter_mut();
+        let root = iter.next().unwrap();
+        iter.next();
+        iter.replace(root, Tree::empty());
+    }
+
+    #[test]
+    fn into_iter() {
+        let sample = || {
+            tree(node(
+                0,
+                [node(1, [leaf(2), null()]), node(3, [leaf(4), leaf(5)])],
+            ))
+        };
+        let mut tree = sample();
+        for v in &mut tree {
+            *v *= 2;
+        }
+        let shared: Vec<_> = (&tree).into_iter().copied().collect();
+        assert_eq!(vec![0, 2, 4, 6, 8, 10], shared);
+        assert_eq!(shared, tree.into_iter().collect::<Vec<_>>());
+
+        // Dropping partway through still frees the rest
+        let mut iter = sample().into_iter();
+        assert_eq!(Some(0), iter.next());
+        assert_eq!(Some(1), iter.next());
+    }
+
+    #[test]
+    fn into_iter_owned() {
+        let tree = tree(node("a".to_owned(), [leaf("b".to_owned()), null()]));
+        assert_eq!(vec!["a", "b"], tree.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[should_panic = "tree is already being iterated over"]
+    fn nested_dfs_iter() {
+        let tree = tree(node(0, [leaf(1), leaf(2)]));
+        let mut outer = tree.dfs_iter();
+        outer.next();
+        tree.dfs_iter().next();
+    }
+
+    #[test]
+    fn clone() {
+        let original = tree(node(
+            "a".to_owned(),
+            [
+                node("b".to_owned(), [null(), leaf("c".to_owned())]),
+                leaf("d".to_owned()),
+            ],
+        ));
+        let mut copy = original.clone();
+        for v in &mut copy {
+            v.push('!');
+        }
+        let values = |tree: &Tree<String, 2>| tree.dfs_iter().cloned().collect::<Vec<_>>();
+        assert_eq!(vec!["a", "b", "c", "d"], values(&original));
+        assert_eq!(vec!["a!", "b!", "c!", "d!"], values(&copy));
+        assert!(Tree::<String, 2>::empty().clone().is_empty());
+    }
+
+    #[test]
+    fn clone_into() {
+        let source = tree(node(0, [node(1, [null(), leaf(2)]), null()]));
+        let mut target = tree(node(5, [leaf(6), node(7, [leaf(8), null()])]));
+        let reused = (target.root, target.find(&[0]));
+        source.clone_into(&mut target);
+        assert_eq!(reused, (target.root, target.find(&[0])));
+        assert_eq!(None, target.get([1]));
+        assert_dfs_valid([0, 1, 2], target);
+
+        let mut target = Tree::empty();
+        source.clone_into(&mut target);
+        assert_dfs_valid([0, 1, 2], target);
+        let mut target = source.clone();
+        Tree::empty().clone_into(&mut target);
+        assert!(target.is_empty());
+    }
+
+    #[test]
+    fn clone_panic() {
+        struct Bomb(u8);
+        impl Clone for Bomb {
+            fn clone(&self) -> Self {
+                assert_ne!(self.0, 2, "boom");
+                Bomb(self.0)
+            }
+        }
+        let original = tree(node(
+            Bomb(0),
+            [node(Bomb(1), [leaf(Bomb(2)), null()]), leaf(Bomb(3))],
+        ));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| original.clone()));
+        assert!(result.is_err());
+        let values: Vec<u8> = original.dfs_iter().map(|b| b.0).collect();
+        assert_eq!(vec![0, 1, 2, 3], values);
     }
 }
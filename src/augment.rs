@@ -0,0 +1,135 @@
+use crate::array_tree::{Node, NodeIter, Tree};
+
+/// A value that summarizes the subtree below it, e.g. its size or the largest
+/// value in it.
+pub trait Augment {
+    type Summary;
+
+    /// The summary for a node with this value, given the summaries of its
+    /// children. There's one entry per child slot, with `None` for empty ones.
+    fn combine(&self, children: &[Option<&Self::Summary>]) -> Self::Summary;
+}
+
+/// A value along with the summary of its subtree. Summaries are filled in by
+/// [`Tree::recompute_augment`] and kept up to date by [`Tree::update`].
+#[derive(Debug, Clone)]
+pub struct Augmented<T: Augment> {
+    val: T,
+    summary: Option<T::Summary>,
+}
+
+impl<T: Augment> Augmented<T> {
+    /// A value with no summary until the tree it's in is recomputed.
+    pub fn new(val: T) -> Self {
+        Self { val, summary: None }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.val
+    }
+
+    /// The summary of the subtree below, if it's been computed.
+    pub fn summary(&self) -> Option<&T::Summary> {
+        self.summary.as_ref()
+    }
+
+    /// For trees that can keep the summary up to date more cheaply than by
+    /// recomputing it.
+    pub(crate) fn set_summary(&mut self, summary: T::Summary) {
+        self.summary = Some(summary);
+    }
+}
+
+/// Recomputes the summary of `node` from its children's.
+///
+/// # Safety
+///
+/// `node` must be live and have its links in place.
+unsafe fn summarize<T: Augment, const N: usize>(node: *mut Node<Augmented<T>, N>) {
+    let children: [Option<&T::Summary>; N] = std::array::from_fn(|i| {
+        let child = unsafe { (*node).children[i].as_untagged().as_ref()? };
+        child.val.summary.as_ref()
+    });
+    let summary = unsafe { (*node).val.val.combine(&children) };
+    unsafe { (*node).val.summary = Some(summary) };
+}
+
+impl<T: Augment, const N: usize> Tree<Augmented<T>, N> {
+    /// Recomputes every summary, from the leaves up.
+    pub fn recompute_augment(&mut self) {
+        // Post-order, so every child is done by the time we see a node
+        for node in NodeIter::<_, N, N>::new(self.root) {
+            // SAFETY: The node is finished so its children are back in place
+            unsafe { summarize(node) };
+        }
+    }
+
+    /// The summary of the whole tree.
+    pub fn root_summary(&self) -> Option<&T::Summary> {
+        self.root()?.summary()
+    }
+
+    /// Changes the value at `path` with `f`, then fixes up the summaries
+    /// between it and the root. Everything else is assumed to be up to date.
+    ///
+    /// Each summary along the way is found from the root again, which is
+    /// quadratic in the depth but needs no stack and leaves the tree intact if
+    /// anything panics.
+    ///
+    /// Panics if there's no node at `path`.
+    pub fn update(&mut self, path: impl AsRef<[usize]>, f: impl FnOnce(&mut T)) {
+        let path = path.as_ref();
+        f(&mut self[path].val);
+        for depth in (0..=path.len()).rev() {
+            let node = self.find(&path[..depth]);
+            // SAFETY: We hold `&mut self` and nothing is being traversed
+            unsafe { summarize(node) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Tracks subtree sizes and the largest value.
+    #[derive(Debug, Clone)]
+    struct Weight(u32);
+
+    impl Augment for Weight {
+        type Summary = (usize, u32);
+
+        fn combine(&self, children: &[Option<&(usize, u32)>]) -> (usize, u32) {
+            children
+                .iter()
+                .flatten()
+                .fold((1, self.0), |(size, max), &&(s, m)| (size + s, max.max(m)))
+        }
+    }
+
+    type Child = Option<Box<Node<Augmented<Weight>, 2>>>;
+
+    fn node(val: u32, children: [Child; 2]) -> Child {
+        Some(Node::alloc(Augmented::new(Weight(val)), children))
+    }
+
+    fn leaf(val: u32) -> Child {
+        node(val, [None, None])
+    }
+
+    #[test]
+    fn recompute_and_update() {
+        let mut tree = Tree::new(node(3, [node(1, [leaf(7), None]), leaf(2)]));
+        assert_eq!(None, tree.root_summary());
+        tree.recompute_augment();
+        assert_eq!(Some(&(4, 7)), tree.root_summary());
+        assert_eq!(Some(&(2, 7)), tree.get([0]).and_then(Augmented::summary));
+
+        tree.update([0, 0], |w| w.0 = 0);
+        assert_eq!(Some(&(4, 3)), tree.root_summary());
+        assert_eq!(Some(&(1, 0)), tree.get([0, 0]).and_then(Augmented::summary));
+        tree.update([1], |w| w.0 = 9);
+        assert_eq!(Some(&(4, 9)), tree.root_summary());
+        assert_eq!(0, tree.get([0, 0]).unwrap().value().0);
+    }
+}
@@ -1,176 +1,98 @@
-use std::{
-    fmt::{self, Debug},
-    marker::PhantomData,
-    ptr::{self},
-};
-
-use crate::tagged_ptr::TaggedPtr;
-
-pub struct Tree<T> {
-    root: *mut Node<T>,
-}
-
-impl<T: Debug> Debug for Tree<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Tree")
-            .field("root", &TaggedPtr::from_untagged(self.root))
-            .finish()
-    }
+//! Binary trees are just [`array_tree`](crate::array_tree)s with two children
+//! per node, the left one first.
+
+use std::mem;
+
+use crate::array_tree;
+
+pub const LEFT: usize = 0;
+pub const RIGHT: usize = 1;
+
+pub type Tree<T> = array_tree::Tree<T, 2>;
+pub type Node<T> = array_tree::Node<T, 2>;
+pub(crate) type NodeIter<'tree, T, const RETURN_ON_VISIT: usize> =
+    array_tree::NodeIter<'tree, T, 2, RETURN_ON_VISIT>;
+pub type DfsIterMut<'tree, T> = array_tree::DfsIterMut<'tree, T, 2>;
+
+/// Takes the nodes of a search tree out, along with the `len` kept next to
+/// it, so they can be rearranged through raw links. Until both are put back
+/// the map reads as empty, so if comparing keys panics partway through, the
+/// nodes are leaked rather than freed half-linked and `len` still matches.
+pub(crate) fn take_for_rearranging<T>(
+    tree: &mut Tree<T>,
+    len: &mut usize,
+) -> (*mut Node<T>, usize) {
+    (mem::take(tree).into_raw(), mem::take(len))
 }
 
-#[derive(Debug)]
-pub struct Node<T> {
-    val: T,
-    left: TaggedPtr<Node<T>>,
-    right: TaggedPtr<Node<T>>,
-}
+/// Callbacks for [`Tree::visit`]. Every node gets all three, even when it's
+/// missing one or both children.
+pub trait BinaryVisitor<T> {
+    /// Before the left subtree.
+    fn pre(&mut self, _val: &T) {}
 
-impl<T> Tree<T> {
-    pub fn new(root: *mut Node<T>) -> Self {
-        Self { root }
-    }
-
-    pub fn dfs_iter_mut(&mut self) -> DfsIterMut<T> {
-        let iter = NodeIter {
-            prev: ptr::null_mut(),
-            cur: self.root,
-            lifetime: PhantomData,
-        };
-        DfsIterMut { iter }
-    }
-}
-
-impl<T> Drop for Tree<T> {
-    fn drop(&mut self) {
-        // We want to visit the leaves first
-        let iter = NodeIter::<T, 2> {
-            prev: ptr::null_mut(),
-            cur: self.root,
-            lifetime: PhantomData,
-        };
-        for node in iter {
-            let _ = unsafe { Box::from_raw(node) };
-        }
-    }
-}
+    /// Between the left and right subtrees.
+    fn in_(&mut self, _val: &T) {}
 
-pub struct NodeIter<'tree, T, const RETURN_ON_VISIT: usize> {
-    prev: *mut Node<T>,
-    cur: *mut Node<T>,
-    lifetime: PhantomData<&'tree T>,
+    /// After the right subtree.
+    fn post(&mut self, _val: &T) {}
 }
 
-// NOTE: It's okay if this doesn't run. The tree will leak some nodes but be
-// safe
-impl<'tree, T, const RETURN_ON_VISIT: usize> Drop for NodeIter<'tree, T, RETURN_ON_VISIT> {
-    fn drop(&mut self) {
-        println!("dropping iter");
-        // TODO: dropping iter needs to fixup the tree
+impl<T> Tree<T> {
+    /// Iterates over the values in order, each one after its left subtree and
+    /// before its right.
+    pub fn inorder_iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        // SAFETY: Traversals never touch values
+        NodeIter::<T, 1>::new(self.root).map(|node| unsafe { &mut (*node).val })
     }
-}
 
-impl<'tree, T, const RETURN_ON_VISIT: usize> Iterator for NodeIter<'tree, T, RETURN_ON_VISIT> {
-    type Item = *mut Node<T>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            // SAFETY: We're guarnteed the pointers live for the lifespan of 'tree
-            let cur: &'tree mut Node<T> = unsafe { self.cur.as_mut()? };
-            match (cur.left.is_seen(), cur.right.is_seen()) {
-                // First time visiting, yield this node
-                (false, false) => {
-                    let old_left = cur.left.as_untagged();
-                    cur.left = TaggedPtr::from_untagged(self.prev).seen();
-                    if old_left.is_null() {
-                        // Pretend like we've just finished the left
-                        self.prev = old_left;
-                    } else {
-                        self.cur = old_left;
-                        self.prev = cur;
-                    }
-                    if RETURN_ON_VISIT == 0 {
-                        return Some(cur);
-                    }
-                }
-                // Second time visiting, just go to the right
-                (true, false) => {
-                    let old_right = cur.right.as_untagged();
-                    cur.right = TaggedPtr::from_untagged(self.prev).seen();
-                    if old_right.is_null() {
-                        // Pretend like we've just finished the right
-                        self.prev = old_right;
-                    } else {
-                        self.cur = old_right;
-                        self.prev = cur;
-                    }
-                    if RETURN_ON_VISIT == 1 {
-                        return Some(cur);
-                    }
-                }
-                // Invalid state. Tho theoretically we could visit the left
-                (false, true) => unreachable!("we always visit left before right"),
-                // Visited this whole subtree, re-construct things and go up
-                (true, true) => {
-                    let real_right = self.prev;
-                    let real_left = cur.right;
-                    let parent = cur.left;
-                    cur.left = real_left.unseen();
-                    cur.right = TaggedPtr::from_untagged(real_right).unseen();
-                    self.cur = parent.as_untagged();
-                    self.prev = cur;
-                    if RETURN_ON_VISIT == 2 {
-                        return Some(cur);
-                    }
-                }
+    /// Walks the tree once, calling into `visitor` before, between and after
+    /// each node's children.
+    ///
+    /// Panics if the tree is partway through a [`Tree::dfs_iter`].
+    pub fn visit(&self, visitor: &mut impl BinaryVisitor<T>) {
+        assert!(!self.is_traversing(), "tree is being iterated over");
+        let mut iter = NodeIter::<T, 0>::new(self.root);
+        while let Some((node, visited)) = iter.step() {
+            // SAFETY: Values aren't touched by the traversal
+            let val = unsafe { &(*node).val };
+            match visited {
+                0 => visitor.pre(val),
+                1 => visitor.in_(val),
+                _ => visitor.post(val),
             }
         }
     }
 }
 
-pub struct DfsIterMut<'tree, T> {
-    iter: NodeIter<'tree, T, 0>,
-}
-
-impl<'tree, T> Iterator for DfsIterMut<'tree, T> {
-    type Item = &'tree mut T;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iter
-            .next()
-            .map(|node| unsafe { &mut node.as_mut().expect("should not be null").val })
-    }
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
 
-    fn assert_dfs_valid<T: Clone + Debug + PartialEq>(
+    type Child<T> = Option<Box<Node<T>>>;
+
+    fn assert_dfs_valid<T: Clone + std::fmt::Debug + PartialEq>(
         expected: impl IntoIterator<Item = T>,
-        root: TaggedPtr<Node<T>>,
+        root: Child<T>,
     ) {
         let expected: Vec<T> = expected.into_iter().collect();
-        let mut tree = Tree::new(root.as_untagged());
+        let mut tree = Tree::new(root);
         let actual: Vec<T> = tree.dfs_iter_mut().map(|v| v.clone()).collect();
         assert_eq!(expected, actual);
     }
 
-    fn null<T>() -> TaggedPtr<Node<T>> {
-        TaggedPtr::from_untagged(ptr::null_mut())
-    }
-
-    fn node<T>(val: T, left: TaggedPtr<Node<T>>, right: TaggedPtr<Node<T>>) -> TaggedPtr<Node<T>> {
-        let node = Node { val, left, right };
-        TaggedPtr::from_untagged(Box::into_raw(Box::new(node)))
+    fn node<T>(val: T, left: Child<T>, right: Child<T>) -> Child<T> {
+        Some(Node::alloc(val, [left, right]))
     }
 
-    fn leaf<T>(val: T) -> TaggedPtr<Node<T>> {
-        node(val, null(), null())
+    fn leaf<T>(val: T) -> Child<T> {
+        node(val, None, None)
     }
 
     #[test]
     fn empty() {
-        assert_dfs_valid::<i32>([], null());
+        assert_dfs_valid::<i32>([], None);
+        assert!(Tree::<i32>::default().is_empty());
     }
 
     #[test]
@@ -180,14 +102,86 @@ mod test {
 
     #[test]
     fn two() {
-        assert_dfs_valid([0, 1], node(0, leaf(1), null()));
+        assert_dfs_valid([0, 1], node(0, leaf(1), None));
     }
 
     #[test]
     fn basic() {
         assert_dfs_valid(
             0..=5,
-            node(0, node(1, leaf(2), null()), node(3, leaf(4), leaf(5))),
+            node(0, node(1, leaf(2), None), node(3, leaf(4), leaf(5))),
         );
     }
+
+    #[test]
+    fn iter_fixes_tree() {
+        let sample = || node(0, node(1, leaf(2), None), node(3, leaf(4), leaf(5)));
+        for stop in 0..=6 {
+            let mut tree = Tree::new(sample());
+            tree.dfs_iter_mut().take(stop).for_each(drop);
+            tree.inorder_iter_mut().take(stop).for_each(drop);
+            tree.postorder_iter_mut().take(stop).for_each(drop);
+            assert!(!tree.is_traversing());
+            let values: Vec<i32> = tree.dfs_iter_mut().map(|v| *v).collect();
+            assert_eq!((0..=5).collect::<Vec<_>>(), values);
+        }
+    }
+
+    #[test]
+    fn postorder() {
+        let mut tree = Tree::new(node(0, node(1, leaf(2), None), node(3, leaf(4), leaf(5))));
+        let post: Vec<i32> = tree.postorder_iter_mut().map(|v| *v).collect();
+        assert_eq!(vec![2, 1, 4, 5, 3, 0], post);
+        assert!(Tree::<i32>::empty().postorder_iter_mut().next().is_none());
+    }
+
+    #[test]
+    fn inorder() {
+        let mut tree = Tree::new(node(3, node(1, leaf(0), leaf(2)), node(5, leaf(4), None)));
+        let mut iter = tree.inorder_iter_mut();
+        *iter.next().unwrap() = 10;
+        assert_eq!(vec![1, 2, 3, 4, 5], iter.map(|v| *v).collect::<Vec<_>>());
+        assert_eq!(
+            vec![3, 1, 10, 2, 5, 4],
+            tree.dfs_iter().copied().collect::<Vec<_>>()
+        );
+        assert!(Tree::<i32>::empty().inorder_iter_mut().next().is_none());
+    }
+
+    #[test]
+    fn visit() {
+        struct Infix(String);
+
+        impl BinaryVisitor<char> for Infix {
+            fn pre(&mut self, val: &char) {
+                if !val.is_alphanumeric() {
+                    self.0.push('(');
+                }
+            }
+
+            fn in_(&mut self, val: &char) {
+                self.0.push(*val);
+            }
+
+            fn post(&mut self, val: &char) {
+                if !val.is_alphanumeric() {
+                    self.0.push(')');
+                }
+            }
+        }
+
+        let tree = Tree::new(node('*', node('+', leaf('a'), leaf('b')), leaf('c')));
+        let mut infix = Infix(String::new());
+        tree.visit(&mut infix);
+        assert_eq!("((a+b)*c)", infix.0);
+    }
+
+    #[test]
+    fn arbitrary() {
+        let data = [7, 3, 0, 1, 2, 0, 4, 5, 6, 9];
+        let (mut tree, expected) = Tree::arbitrary(&data);
+        assert_eq!(7, expected[0]);
+        let actual: Vec<u8> = tree.dfs_iter_mut().map(|v| *v).collect();
+        assert_eq!(expected, actual);
+    }
 }
@@ -0,0 +1,91 @@
+//! Saving how far a preorder walk has got along with the tree itself, so a
+//! long job can pick up where it left off in another process.
+//!
+//! To save partway through, let go of the walk, [`save`], and carry on with
+//! [`Tree::dfs_iter_mut_from`]. Getting back to the same place only steps
+//! through links, so it's much cheaper than redoing the work.
+
+use std::io::{self, Read, Write};
+
+use crate::array_tree::Tree;
+
+/// Writes `position`, how many values a [`Tree::dfs_iter_mut`] walk has dealt
+/// with, followed by the tree as [`Tree::encode_to`] writes it.
+///
+/// Positions only line up for walks that visit everything, since skipping or
+/// replacing subtrees changes which values come next.
+pub fn save<T, const N: usize, W: Write>(
+    tree: &Tree<T, N>,
+    position: usize,
+    writer: &mut W,
+    encode: impl FnMut(&T, &mut W) -> io::Result<()>,
+) -> io::Result<()> {
+    writer.write_all(&(position as u64).to_le_bytes())?;
+    tree.encode_to(writer, encode)
+}
+
+/// Reads back what [`save`] wrote, giving the tree and the position to pass to
+/// [`Tree::dfs_iter_mut_from`].
+pub fn load<T, const N: usize, R: Read>(
+    reader: &mut R,
+    decode: impl FnMut(&mut R) -> io::Result<T>,
+) -> io::Result<(Tree<T, N>, usize)> {
+    let mut position = [0; 8];
+    reader.read_exact(&mut position)?;
+    let position = usize::try_from(u64::from_le_bytes(position))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "position is too big"))?;
+    Ok((Tree::decode_from(reader, decode)?, position))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::array_tree::Node;
+
+    fn encode(val: &u8, writer: &mut Vec<u8>) -> io::Result<()> {
+        writer.write_all(&[*val])
+    }
+
+    fn decode(reader: &mut &[u8]) -> io::Result<u8> {
+        let mut val = [0];
+        reader.read_exact(&mut val)?;
+        Ok(val[0])
+    }
+
+    #[test]
+    fn resumes() {
+        let leaf = |val| Some(Node::alloc(val, [None, None]));
+        let mut tree = Tree::new(Some(Node::alloc(
+            1,
+            [Some(Node::alloc(2, [leaf(3), None])), leaf(4)],
+        )));
+        // Crash after doing the first two
+        for val in tree.dfs_iter_mut().take(2) {
+            *val *= 10;
+        }
+        let mut saved = Vec::new();
+        save(&tree, 2, &mut saved, encode).unwrap();
+        drop(tree);
+
+        let (mut tree, position) = load::<u8, 2, _>(&mut &saved[..], decode).unwrap();
+        assert_eq!(2, position);
+        for val in tree.dfs_iter_mut_from(position) {
+            *val *= 10;
+        }
+        assert_eq!(
+            vec![10, 20, 30, 40],
+            tree.dfs_iter().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(0, tree.dfs_iter_mut_from(4).count());
+    }
+
+    #[test]
+    fn truncated() {
+        let tree = Tree::new(Some(Node::alloc(1, [None, None])));
+        let mut saved = Vec::new();
+        save(&tree, 1, &mut saved, encode).unwrap();
+        for len in 0..saved.len() {
+            assert!(load::<u8, 2, _>(&mut &saved[..len], decode).is_err());
+        }
+    }
+}
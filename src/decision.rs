@@ -0,0 +1,132 @@
+use crate::array_tree::Tree;
+
+/// A node of a decision tree. Splits choose which child to descend into and
+/// leaves hold the prediction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision<S, O> {
+    Split(S),
+    Leaf(O),
+}
+
+/// Picks the child a feature vector should descend into.
+pub trait Split<F: ?Sized> {
+    fn branch(&self, features: &F) -> usize;
+}
+
+/// Sends feature vectors with `features[feature] < value` to the first child
+/// and everything else to the second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Threshold {
+    pub feature: usize,
+    pub value: f64,
+}
+
+impl Split<[f64]> for Threshold {
+    fn branch(&self, features: &[f64]) -> usize {
+        if features[self.feature] < self.value {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+impl<S, O, const N: usize> Tree<Decision<S, O>, N> {
+    /// Follows the splits from the root down to a leaf in O(depth) time.
+    /// Returns `None` if a split sends us to a missing child.
+    ///
+    /// Panics if a split returns a branch that isn't less than `N`, or if the
+    /// tree is partway through a [`Tree::dfs_iter`].
+    pub fn predict<F: ?Sized>(&self, features: &F) -> Option<&O>
+    where
+        S: Split<F>,
+    {
+        assert!(!self.is_traversing(), "tree is being iterated over");
+        // SAFETY: No traversal is in progress so the links are all in place
+        let mut node = unsafe { self.root.as_ref()? };
+        loop {
+            match &node.val {
+                Decision::Leaf(out) => return Some(out),
+                Decision::Split(split) => {
+                    let child = node.children[split.branch(features)];
+                    node = unsafe { child.as_untagged().as_ref()? };
+                }
+            }
+        }
+    }
+
+    /// Predicts every row in turn.
+    pub fn predict_batch<'a, F: ?Sized + 'a>(
+        &'a self,
+        rows: impl IntoIterator<Item = &'a F> + 'a,
+    ) -> impl Iterator<Item = Option<&'a O>> + 'a
+    where
+        S: Split<F>,
+    {
+        rows.into_iter().map(|row| self.predict(row))
+    }
+
+    /// Visits every leaf's output in DFS order, e.g. to recalibrate them in
+    /// bulk.
+    pub fn outputs_mut(&mut self) -> impl Iterator<Item = &mut O> {
+        self.dfs_iter_mut().filter_map(|node| match node {
+            Decision::Split(_) => None,
+            Decision::Leaf(out) => Some(out),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::array_tree::Node;
+
+    type Model = Tree<Decision<Threshold, &'static str>, 2>;
+
+    fn split(feature: usize, value: f64) -> Decision<Threshold, &'static str> {
+        Decision::Split(Threshold { feature, value })
+    }
+
+    fn leaf(out: &'static str) -> Option<Box<Node<Decision<Threshold, &'static str>, 2>>> {
+        Some(Node::alloc(Decision::Leaf(out), [None, None]))
+    }
+
+    fn model() -> Model {
+        // x < 1 ? (y < 5 ? a : b) : c
+        Tree::new(Some(Node::alloc(
+            split(0, 1.0),
+            [
+                Some(Node::alloc(split(1, 5.0), [leaf("a"), leaf("b")])),
+                leaf("c"),
+            ],
+        )))
+    }
+
+    #[test]
+    fn predict() {
+        let model = model();
+        assert_eq!(Some(&"a"), model.predict(&[0.0, 0.0][..]));
+        assert_eq!(Some(&"b"), model.predict(&[0.0, 9.0][..]));
+        assert_eq!(Some(&"c"), model.predict(&[3.0, 0.0][..]));
+    }
+
+    #[test]
+    fn missing_child() {
+        let model: Model = Tree::new(Some(Node::alloc(split(0, 1.0), [leaf("a"), None])));
+        assert_eq!(None, model.predict(&[2.0][..]));
+        assert_eq!(None, Model::new(None).predict(&[2.0][..]));
+    }
+
+    #[test]
+    fn batch() {
+        let mut model = model();
+        let rows: [&[f64]; 3] = [&[3.0, 0.0], &[0.0, 0.0], &[0.5, 6.0]];
+        let outs: Vec<_> = model.predict_batch(rows).collect();
+        assert_eq!(vec![Some(&"c"), Some(&"a"), Some(&"b")], outs);
+
+        for out in model.outputs_mut() {
+            *out = "z";
+        }
+        assert_eq!(Some(&"z"), model.predict(&[0.0, 0.0][..]));
+    }
+}
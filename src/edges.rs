@@ -0,0 +1,73 @@
+use crate::array_tree::{self, NodeIter, Tree};
+
+/// A value along with data for the edges down to each of its children, e.g.
+/// weights or labels. `edges[i]` belongs to the edge into child `i` and is
+/// kept even while that slot is empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Labeled<T, E, const N: usize> {
+    pub val: T,
+    pub edges: [E; N],
+}
+
+impl<T, E, const N: usize> Tree<Labeled<T, E, N>, N> {
+    /// The data on the edge leading into the node at `path`, or `None` for the
+    /// root or a missing node.
+    pub fn edge(&self, path: impl AsRef<[usize]>) -> Option<&E> {
+        let (&i, parent) = path.as_ref().split_last()?;
+        self.get(path.as_ref())?;
+        Some(&self.get(parent)?.edges[i])
+    }
+
+    /// Calls `f` with every edge in preorder of the nodes they lead into,
+    /// along with the values at either end.
+    pub fn for_each_edge(&mut self, mut f: impl FnMut(&T, &E, &mut T)) {
+        let mut iter = NodeIter::<_, N, 0>::new(self.root);
+        while let Some((node, visited)) = iter.step() {
+            if visited != 0 {
+                continue;
+            }
+            // SAFETY: We've only just stepped into this node
+            let Some((parent, i)) = (unsafe { array_tree::ancestors(node) }).next() else {
+                continue;
+            };
+            // Only values are borrowed, and the parent and child are distinct
+            let parent = unsafe { &(*parent).val };
+            let child = unsafe { &mut (*node).val.val };
+            f(&parent.val, &parent.edges[i], child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::array_tree::Node;
+
+    type Stop = Option<Box<Node<Labeled<u32, u32, 2>, 2>>>;
+
+    fn stop(edges: [u32; 2], children: [Stop; 2]) -> Stop {
+        Some(Node::alloc(Labeled { val: 0, edges }, children))
+    }
+
+    fn sample() -> Tree<Labeled<u32, u32, 2>, 2> {
+        let end = || stop([0, 0], [None, None]);
+        Tree::new(stop([3, 5], [end(), stop([7, 0], [end(), None])]))
+    }
+
+    #[test]
+    fn distances() {
+        let mut tree = sample();
+        tree.for_each_edge(|parent, weight, child| *child = parent + weight);
+        let distances: Vec<u32> = tree.dfs_iter().map(|l| l.val).collect();
+        assert_eq!(vec![0, 3, 5, 12], distances);
+    }
+
+    #[test]
+    fn edge() {
+        let tree = sample();
+        assert_eq!(Some(&5), tree.edge([1]));
+        assert_eq!(Some(&7), tree.edge([1, 0]));
+        assert_eq!(None, tree.edge([1, 1]));
+        assert_eq!(None, tree.edge([]));
+    }
+}
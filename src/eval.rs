@@ -0,0 +1,106 @@
+use std::ptr;
+
+use crate::{
+    array_tree::{NodeIter, Tree},
+    tagged_ptr::TaggedPtr,
+};
+
+/// Evaluates a tree bottom-up, returning the root's result.
+///
+/// `reduce` is called on every node in post-order with the results of its
+/// children, and overwrites the node with its own result. Results are stored
+/// back into the tree, so `T` has to be able to represent them (think an AST
+/// with a literal variant). Leaves are reduced too, with no children, and are
+/// free to leave themselves as they are.
+///
+/// Each child is freed once its parent has been reduced, so nothing but the
+/// tree itself is ever allocated.
+pub fn eval<T, const N: usize>(
+    mut tree: Tree<T, N>,
+    mut reduce: impl FnMut(&mut T, [Option<T>; N]),
+) -> Option<T> {
+    // If `reduce` panics the iterator puts things back for the tree to free
+    for node in NodeIter::<T, N, N>::new(tree.root) {
+        // SAFETY: The node is finished so its children are back in place, and
+        // they've all been reduced down to a single node already
+        let node = unsafe { &mut *node };
+        let results = node.children.each_mut().map(|slot| {
+            let child = std::mem::replace(slot, TaggedPtr::from_untagged(ptr::null_mut()));
+            let child = child.as_untagged();
+            (!child.is_null()).then(|| unsafe { Box::from_raw(child) }.val)
+        });
+        reduce(&mut node.val, results);
+    }
+    let root = std::mem::replace(&mut tree.root, ptr::null_mut());
+    (!root.is_null()).then(|| unsafe { Box::from_raw(root.cast_mut()) }.val)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::array_tree::Node;
+
+    #[derive(Debug, PartialEq)]
+    enum Op {
+        Num(i64),
+        Neg,
+        Sub,
+    }
+
+    fn calc(op: &mut Op, [lhs, rhs]: [Option<Op>; 2]) {
+        let val = match (&op, lhs, rhs) {
+            (Op::Num(n), None, None) => *n,
+            (Op::Neg, Some(Op::Num(n)), None) => -n,
+            (Op::Sub, Some(Op::Num(a)), Some(Op::Num(b))) => a - b,
+            other => panic!("malformed expression {other:?}"),
+        };
+        *op = Op::Num(val);
+    }
+
+    fn num(n: i64) -> Option<Box<Node<Op, 2>>> {
+        Some(Node::alloc(Op::Num(n), [None, None]))
+    }
+
+    #[test]
+    fn calculator() {
+        // -(10 - 3) - (-2)
+        let tree = Tree::new(Some(Node::alloc(
+            Op::Sub,
+            [
+                Some(Node::alloc(
+                    Op::Neg,
+                    [Some(Node::alloc(Op::Sub, [num(10), num(3)])), None],
+                )),
+                Some(Node::alloc(Op::Neg, [num(2), None])),
+            ],
+        )));
+        assert_eq!(Some(Op::Num(-5)), eval(tree, calc));
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(None, eval(Tree::new(None), calc));
+    }
+
+    #[test]
+    fn counts_leaves() {
+        let tree = Tree::new(Some(Node::alloc(
+            0,
+            [
+                Some(Node::alloc(0, [None, None, None])),
+                None,
+                Some(Node::alloc(
+                    0,
+                    [Some(Node::alloc(0, [None, None, None])), None, None],
+                )),
+            ],
+        )));
+        let leaves = eval(tree, |count, children| {
+            *count = children.iter().flatten().sum::<usize>();
+            if *count == 0 {
+                *count = 1;
+            }
+        });
+        assert_eq!(Some(2), leaves);
+    }
+}
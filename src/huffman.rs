@@ -0,0 +1,154 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::array_tree::{self, Node, NodeIter, Tree};
+
+/// A prefix code, read from the most significant of its `len` low bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Code {
+    pub bits: u64,
+    pub len: u32,
+}
+
+/// A Huffman coding tree. Leaves hold symbols and each edge is a bit, `0` for
+/// the first child and `1` for the second.
+pub struct Huffman<S> {
+    tree: Tree<Option<S>, 2>,
+}
+
+impl<S> Huffman<S> {
+    /// Builds the optimal code for the given symbol frequencies. Ties are
+    /// broken by the order symbols were given in.
+    pub fn new(freqs: impl IntoIterator<Item = (S, u64)>) -> Self {
+        let mut subtrees = Vec::new();
+        let mut heap = BinaryHeap::new();
+        for (symbol, freq) in freqs {
+            heap.push((Reverse(freq), Reverse(subtrees.len())));
+            subtrees.push(Some(Node::alloc(Some(symbol), [None, None])));
+        }
+        let root = loop {
+            let Some((Reverse(freq), Reverse(i))) = heap.pop() else {
+                break None;
+            };
+            let Some((Reverse(other_freq), Reverse(j))) = heap.pop() else {
+                let root = subtrees[i].take();
+                // A lone symbol still needs a bit to be written at all
+                let is_leaf = root.as_ref().is_some_and(|n| n.val.is_some());
+                break if is_leaf {
+                    Some(Node::alloc(None, [root, None]))
+                } else {
+                    root
+                };
+            };
+            let merged = Node::alloc(None, [subtrees[i].take(), subtrees[j].take()]);
+            heap.push((Reverse(freq + other_freq), Reverse(subtrees.len())));
+            subtrees.push(Some(merged));
+        };
+        Self {
+            tree: Tree::new(root),
+        }
+    }
+
+    /// Calls `f` with every symbol and its code, in code order.
+    ///
+    /// Panics if a code is longer than 64 bits.
+    pub fn for_each_code(&mut self, mut f: impl FnMut(&S, Code)) {
+        let mut iter = NodeIter::<_, 2, 0>::new(self.tree.root);
+        while let Some((node, visited)) = iter.step() {
+            if visited != 0 {
+                continue;
+            }
+            // SAFETY: We've only just stepped into this node
+            let Some(symbol) = (unsafe { &(*node).val }) else {
+                continue;
+            };
+            let mut code = Code { bits: 0, len: 0 };
+            for (_, bit) in unsafe { array_tree::ancestors(node) } {
+                assert!(code.len < u64::BITS, "code is too long");
+                code.bits |= (bit as u64) << code.len;
+                code.len += 1;
+            }
+            f(symbol, code);
+        }
+    }
+
+    /// Decodes `bits`, calling `f` with each symbol. Returns false if the bits
+    /// ended partway through a code.
+    pub fn decode(&self, bits: impl IntoIterator<Item = bool>, mut f: impl FnMut(&S)) -> bool {
+        let mut node = self.tree.root;
+        for bit in bits {
+            // SAFETY: No traversal is in progress since we hold `&self`
+            let Some(cur) = (unsafe { node.as_ref() }) else {
+                return false;
+            };
+            node = cur.children[bit as usize].as_untagged();
+            if let Some(symbol) = unsafe { node.as_ref() }.and_then(|n| n.val.as_ref()) {
+                f(symbol);
+                node = self.tree.root;
+            }
+        }
+        node == self.tree.root
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn codes(huffman: &mut Huffman<char>) -> Vec<(char, String)> {
+        let mut codes = Vec::new();
+        huffman.for_each_code(|&symbol, code| {
+            let bits = (0..code.len)
+                .rev()
+                .map(|i| if code.bits >> i & 1 == 1 { '1' } else { '0' })
+                .collect();
+            codes.push((symbol, bits));
+        });
+        codes
+    }
+
+    #[test]
+    fn textbook() {
+        let freqs = [
+            ('a', 45),
+            ('b', 13),
+            ('c', 12),
+            ('d', 16),
+            ('e', 9),
+            ('f', 5),
+        ];
+        let mut huffman = Huffman::new(freqs);
+        let mut codes = codes(&mut huffman);
+        codes.sort();
+        let lengths: Vec<_> = codes.iter().map(|(s, c)| (*s, c.len())).collect();
+        assert_eq!(
+            vec![('a', 1), ('b', 3), ('c', 3), ('d', 3), ('e', 4), ('f', 4)],
+            lengths
+        );
+
+        let message = "fabcade";
+        let bits: Vec<bool> = message
+            .chars()
+            .flat_map(|ch| {
+                let (_, code) = codes.iter().find(|(s, _)| *s == ch).unwrap();
+                code.chars().map(|b| b == '1').collect::<Vec<_>>()
+            })
+            .collect();
+        let mut decoded = String::new();
+        assert!(huffman.decode(bits.iter().copied(), |&s| decoded.push(s)));
+        assert_eq!(message, decoded);
+        assert!(!huffman.decode(bits[..bits.len() - 1].iter().copied(), |_| {}));
+    }
+
+    #[test]
+    fn single_symbol() {
+        let mut huffman = Huffman::new([('x', 3)]);
+        assert_eq!(vec![('x', "0".to_owned())], codes(&mut huffman));
+    }
+
+    #[test]
+    fn empty() {
+        let mut huffman = Huffman::<char>::new([]);
+        assert!(codes(&mut huffman).is_empty());
+        assert!(huffman.decode([], |_| {}));
+    }
+}
@@ -0,0 +1,173 @@
+use std::ops::Range;
+
+use crate::{
+    augment::{Augment, Augmented},
+    binary_tree::{Node, NodeIter, Tree, LEFT, RIGHT},
+};
+
+#[derive(Debug)]
+struct Entry<K, V> {
+    range: Range<K>,
+    value: V,
+}
+
+impl<K: Ord + Clone, V> Augment for Entry<K, V> {
+    /// The furthest any range in the subtree reaches
+    type Summary = K;
+
+    fn combine(&self, children: &[Option<&K>]) -> K {
+        let end = children.iter().flatten().copied().max();
+        end.map_or(&self.range.end, |end| end.max(&self.range.end))
+            .clone()
+    }
+}
+
+/// Half-open ranges with values attached, for finding every range that
+/// overlaps another.
+///
+/// Entries are kept in a binary search tree ordered by where they start,
+/// which isn't rebalanced. Each node remembers the furthest any range below it
+/// reaches, so queries can skip subtrees that end too soon.
+pub struct IntervalTree<K: Ord + Clone, V> {
+    tree: Tree<Augmented<Entry<K, V>>>,
+    len: usize,
+}
+
+impl<K: Ord + Clone, V> IntervalTree<K, V> {
+    pub fn new() -> Self {
+        Self {
+            tree: Tree::empty(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds `range` with `value` attached. Ranges may repeat.
+    ///
+    /// A new range can only push summaries further out, so they're raised on
+    /// the way down and nothing is revisited.
+    pub fn insert(&mut self, range: Range<K>, value: V) {
+        let mut slot = None;
+        let mut node = self.tree.root.cast_mut();
+        // SAFETY: We hold `&mut self` and nothing is being traversed
+        while let Some(cur) = unsafe { node.as_mut() } {
+            // If comparing panics partway, the summaries above are only too
+            // far out, which costs queries time but never misses anything
+            if cur.val.summary().is_none_or(|end| *end < range.end) {
+                cur.val.set_summary(range.end.clone());
+            }
+            let i = if range.start < cur.val.value().range.start {
+                LEFT
+            } else {
+                RIGHT
+            };
+            node = cur.children[i].as_untagged();
+            slot = Some(&mut cur.children[i]);
+        }
+
+        let end = range.end.clone();
+        let mut entry = Augmented::new(Entry { range, value });
+        entry.set_summary(end);
+        let leaf = Node::alloc(entry, [None, None]);
+        match slot {
+            None => self.tree = Tree::new(Some(leaf)),
+            Some(slot) => *slot = slot.retarget(Box::into_raw(leaf)),
+        }
+        self.len += 1;
+    }
+
+    /// Calls `f` on every entry whose range overlaps `range`, in order of
+    /// where they start.
+    ///
+    /// Panics if called from within `f`.
+    pub fn query_overlaps(&self, range: Range<K>, mut f: impl FnMut(&Range<K>, &V)) {
+        assert!(!self.tree.is_traversing(), "tree is being iterated over");
+        // Going in order is going by start
+        let mut iter = NodeIter::<_, 0>::new(self.tree.root);
+        while let Some((node, visited)) = iter.step() {
+            // SAFETY: Values aren't touched by the traversal
+            let augmented = unsafe { &(*node).val };
+            if visited == 0 {
+                // Nothing below reaches far enough
+                if augmented.summary().is_none_or(|end| *end <= range.start) {
+                    iter.skip_children(node);
+                }
+                continue;
+            }
+            if visited != 1 {
+                continue;
+            }
+            let entry = augmented.value();
+            if entry.range.start < range.end && range.start < entry.range.end {
+                f(&entry.range, &entry.value);
+            }
+        }
+    }
+}
+
+impl<K: Ord + Clone, V> Default for IntervalTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn overlaps() {
+        let ranges = [5..10, 0..3, 8..20, 2..6, 15..16, 12..13, 1..30, 7..8];
+        let mut tree = IntervalTree::new();
+        for (i, range) in ranges.iter().enumerate() {
+            tree.insert(range.clone(), i);
+        }
+        assert_eq!(ranges.len(), tree.len());
+
+        for query in [0..1, 3..5, 9..12, 16..18, 30..40, 6..6, 0..100] {
+            let mut found = Vec::new();
+            tree.query_overlaps(query.clone(), |range, &i| found.push((range.start, i)));
+            let mut expected: Vec<_> = (ranges.iter().enumerate())
+                .filter(|(_, r)| r.start < query.end && query.start < r.end)
+                .map(|(i, r)| (r.start, i))
+                .collect();
+            expected.sort();
+            assert_eq!(expected, found, "querying {query:?}");
+        }
+    }
+
+    #[test]
+    fn summaries_match_recomputing() {
+        let mut tree = IntervalTree::new();
+        // Sorted starts make the tree a list, which insert has to cope with
+        for i in 0..2000u32 {
+            tree.insert(i..i + (i * 7919) % 50, ());
+        }
+        let raised: Vec<u32> = tree
+            .tree
+            .dfs_iter()
+            .map(|v| *v.summary().unwrap())
+            .collect();
+        tree.tree.recompute_augment();
+        let recomputed: Vec<u32> = tree
+            .tree
+            .dfs_iter()
+            .map(|v| *v.summary().unwrap())
+            .collect();
+        assert_eq!(recomputed, raised);
+    }
+
+    #[test]
+    fn empty() {
+        let tree = IntervalTree::<u32, ()>::default();
+        assert!(tree.is_empty());
+        tree.query_overlaps(0..10, |_, _| unreachable!());
+    }
+}
@@ -0,0 +1,82 @@
+use std::borrow::Borrow;
+
+use crate::array_tree::Tree;
+
+/// Helpers for trees used as hierarchical maps, like config trees or
+/// namespaces, where every value is a `(key, value)` pair. Keys needn't be
+/// unique, lookups find the first in preorder.
+impl<K, V, const N: usize> Tree<(K, V), N> {
+    /// Searches in preorder, stopping at the first match.
+    pub fn find_by_key<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: PartialEq + ?Sized,
+    {
+        self.dfs_iter()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn find_by_key_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: PartialEq + ?Sized,
+    {
+        self.dfs_iter_mut()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.dfs_iter().map(|(k, _)| k)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.dfs_iter_mut().map(|(_, v)| v)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::array_tree::Node;
+
+    type Entry = Option<Box<Node<(String, u32), 2>>>;
+
+    fn entry(key: &str, val: u32, children: [Entry; 2]) -> Entry {
+        Some(Node::alloc((key.to_owned(), val), children))
+    }
+
+    fn config() -> Tree<(String, u32), 2> {
+        Tree::new(entry(
+            "root",
+            0,
+            [
+                entry("net", 1, [entry("port", 80, [None, None]), None]),
+                entry("port", 22, [None, None]),
+            ],
+        ))
+    }
+
+    #[test]
+    fn find() {
+        let mut tree = config();
+        assert_eq!(Some(&80), tree.find_by_key("port"));
+        assert_eq!(None, tree.find_by_key("missing"));
+        *tree.find_by_key_mut("net").unwrap() = 2;
+        assert_eq!(Some(&2), tree.find_by_key("net"));
+        // The early exit put the tree back together
+        assert_eq!(
+            vec!["root", "net", "port", "port"],
+            tree.keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn values_mut() {
+        let mut tree = config();
+        tree.values_mut().for_each(|v| *v += 1);
+        let values: Vec<u32> = tree.dfs_iter().map(|(_, v)| *v).collect();
+        assert_eq!(vec![1, 2, 81, 23], values);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::array_tree::{NodeIter, Tree};
+
+const NONE: usize = usize::MAX;
+/// The minimum horizontal gap between neighbouring nodes
+const DISTANCE: f64 = 1.0;
+
+/// Where a node should be drawn. `y` is the node's depth.
+///
+/// The rest of the struct is scratch space for working out `x`, which is what
+/// lets the layout run without allocating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+    // All indices are preorder positions
+    parent: usize,
+    first_child: usize,
+    last_child: usize,
+    prev_sibling: usize,
+    /// Which child of the parent this is, not counting empty slots
+    number: usize,
+    prelim: f64,
+    modifier: f64,
+    thread: usize,
+    ancestor: usize,
+    /// The default ancestor used while placing this node's children
+    default_ancestor: usize,
+    change: f64,
+    shift: f64,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            parent: NONE,
+            first_child: NONE,
+            last_child: NONE,
+            prev_sibling: NONE,
+            number: 0,
+            prelim: 0.0,
+            modifier: 0.0,
+            thread: NONE,
+            ancestor: NONE,
+            default_ancestor: NONE,
+            change: 0.0,
+            shift: 0.0,
+        }
+    }
+}
+
+/// Lays the tree out tidily following Reingold and Tilford, writing each
+/// node's position into `out` at its preorder index. Returns the number of
+/// nodes.
+///
+/// Siblings keep their relative order (empty child slots are ignored),
+/// parents are centered over their children and subtrees are packed as
+/// closely as possible. The leftmost node ends up at `x = 0`.
+///
+/// This is the linear time variant from Buchheim, Jünger and Leipert's
+/// "Improving Walker's Algorithm to Run in Linear Time". The first walk rides on
+/// a post-order traversal and the second is a plain preorder sweep over `out`.
+///
+/// Panics if `out` is shorter than the number of nodes.
+pub fn layout<T, const N: usize>(tree: &mut Tree<T, N>, out: &mut [Position]) -> usize {
+    let mut len = 0;
+    let mut cur = NONE;
+    let mut depth = 0;
+    let mut iter = NodeIter::<T, N, 0>::new(tree.root);
+    while let Some((_, visited)) = iter.step() {
+        if visited == 0 {
+            assert!(len < out.len(), "layout slice is too short");
+            let i = len;
+            len += 1;
+            let prev_sibling = if cur == NONE {
+                NONE
+            } else {
+                out[cur].last_child
+            };
+            out[i] = Position {
+                y: depth as f64,
+                parent: cur,
+                prev_sibling,
+                number: if prev_sibling == NONE {
+                    0
+                } else {
+                    out[prev_sibling].number + 1
+                },
+                ancestor: i,
+                ..Default::default()
+            };
+            if cur != NONE {
+                if out[cur].first_child == NONE {
+                    out[cur].first_child = i;
+                }
+                out[cur].last_child = i;
+            }
+            cur = i;
+            depth += 1;
+        }
+        if visited == N {
+            let parent = out[cur].parent;
+            first_walk(out, cur);
+            cur = parent;
+            depth -= 1;
+        }
+    }
+
+    let out = &mut out[..len];
+    let mut min_x = 0.0f64;
+    for i in 0..out.len() {
+        let p = out[i].parent;
+        let offset = if p == NONE {
+            0.0
+        } else {
+            out[p].x - out[p].prelim + out[p].modifier
+        };
+        out[i].x = out[i].prelim + offset;
+        min_x = min_x.min(out[i].x);
+    }
+    for pos in out {
+        pos.x -= min_x;
+    }
+    len
+}
+
+/// Places `v` relative to its siblings, once all of its children have been.
+fn first_walk(out: &mut [Position], v: usize) {
+    let left = out[v].prev_sibling;
+    if out[v].first_child == NONE {
+        out[v].prelim = if left == NONE {
+            0.0
+        } else {
+            out[left].prelim + DISTANCE
+        };
+    } else {
+        execute_shifts(out, v);
+        let (first, last) = (out[v].first_child, out[v].last_child);
+        let mid = (out[first].prelim + out[last].prelim) / 2.0;
+        if left == NONE {
+            out[v].prelim = mid;
+        } else {
+            out[v].prelim = out[left].prelim + DISTANCE;
+            out[v].modifier = out[v].prelim - mid;
+        }
+    }
+
+    let parent = out[v].parent;
+    if parent != NONE {
+        let default = if left == NONE {
+            v
+        } else {
+            out[parent].default_ancestor
+        };
+        out[parent].default_ancestor = apportion(out, v, default);
+    }
+}
+
+fn next_left(out: &[Position], v: usize) -> usize {
+    match out[v].first_child {
+        NONE => out[v].thread,
+        child => child,
+    }
+}
+
+fn next_right(out: &[Position], v: usize) -> usize {
+    match out[v].last_child {
+        NONE => out[v].thread,
+        child => child,
+    }
+}
+
+/// Pushes `v`'s subtree right until it clears all of its left siblings'.
+fn apportion(out: &mut [Position], v: usize, mut default: usize) -> usize {
+    let left = out[v].prev_sibling;
+    if left == NONE {
+        return default;
+    }
+    // Inner and outer contours on the (i)nside and (o)utside, (l)eft and (r)ight
+    let (mut vir, mut vor, mut vil) = (v, v, left);
+    let mut vol = out[out[v].parent].first_child;
+    let mut sir = out[vir].modifier;
+    let mut sor = out[vor].modifier;
+    let mut sil = out[vil].modifier;
+    let mut sol = out[vol].modifier;
+    while next_right(out, vil) != NONE && next_left(out, vir) != NONE {
+        vil = next_right(out, vil);
+        vir = next_left(out, vir);
+        vol = next_left(out, vol);
+        vor = next_right(out, vor);
+        out[vor].ancestor = v;
+        let shift = (out[vil].prelim + sil) - (out[vir].prelim + sir) + DISTANCE;
+        if shift > 0.0 {
+            let ancestor = out[vil].ancestor;
+            let wl = if out[ancestor].parent == out[v].parent {
+                ancestor
+            } else {
+                default
+            };
+            move_subtree(out, wl, v, shift);
+            sir += shift;
+            sor += shift;
+        }
+        sil += out[vil].modifier;
+        sir += out[vir].modifier;
+        sol += out[vol].modifier;
+        sor += out[vor].modifier;
+    }
+    if next_right(out, vil) != NONE && next_right(out, vor) == NONE {
+        out[vor].thread = next_right(out, vil);
+        out[vor].modifier += sil - sor;
+    }
+    if next_left(out, vir) != NONE && next_left(out, vol) == NONE {
+        out[vol].thread = next_left(out, vir);
+        out[vol].modifier += sir - sol;
+        default = v;
+    }
+    default
+}
+
+fn move_subtree(out: &mut [Position], wl: usize, wr: usize, shift: f64) {
+    let subtrees = (out[wr].number - out[wl].number) as f64;
+    out[wr].change -= shift / subtrees;
+    out[wr].shift += shift;
+    out[wl].change += shift / subtrees;
+    out[wr].prelim += shift;
+    out[wr].modifier += shift;
+}
+
+/// Spreads the shifts recorded by [`move_subtree`] over the children in between.
+fn execute_shifts(out: &mut [Position], v: usize) {
+    let (mut shift, mut change) = (0.0, 0.0);
+    let mut w = out[v].last_child;
+    while w != NONE {
+        out[w].prelim += shift;
+        out[w].modifier += shift;
+        change += out[w].change;
+        shift += out[w].shift + change;
+        w = out[w].prev_sibling;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::array_tree::Node;
+
+    type N3 = Option<Box<Node<(), 3>>>;
+
+    fn n(children: [N3; 3]) -> N3 {
+        Some(Node::alloc((), children))
+    }
+
+    fn leaf() -> N3 {
+        n([None, None, None])
+    }
+
+    fn run(root: N3) -> Vec<(f64, f64)> {
+        let mut tree = Tree::new(root);
+        let mut out = vec![Position::default(); 64];
+        let len = layout(&mut tree, &mut out);
+        let positions: Vec<_> = out[..len].iter().map(|p| (p.x, p.y)).collect();
+        check(&out[..len]);
+        positions
+    }
+
+    /// Parents are centered and nothing on a level overlaps.
+    fn check(out: &[Position]) {
+        for (i, pos) in out.iter().enumerate() {
+            if pos.first_child != NONE {
+                let mid = (out[pos.first_child].x + out[pos.last_child].x) / 2.0;
+                assert!((pos.x - mid).abs() < 1e-9, "node {i} isn't centered");
+            }
+            for other in &out[i + 1..] {
+                if other.y == pos.y {
+                    assert!((other.x - pos.x).abs() >= DISTANCE - 1e-9, "overlap");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fan() {
+        let positions = run(n([leaf(), leaf(), leaf()]));
+        assert_eq!(
+            vec![(1.0, 0.0), (0.0, 1.0), (1.0, 1.0), (2.0, 1.0)],
+            positions
+        );
+    }
+
+    #[test]
+    fn empty_slots_are_ignored() {
+        let positions = run(n([None, leaf(), leaf()]));
+        assert_eq!(vec![(0.5, 0.0), (0.0, 1.0), (1.0, 1.0)], positions);
+        assert_eq!(Vec::<(f64, f64)>::new(), run(None));
+    }
+
+    #[test]
+    fn subtrees_are_separated() {
+        // The left child's subtree juts out right and the right child's juts
+        // out left, so they have to be pushed apart below the top level
+        let left = n([None, None, n([None, None, n([leaf(), leaf(), leaf()])])]);
+        let right = n([n([n([leaf(), leaf(), leaf()]), None, None]), None, None]);
+        let positions = run(n([left, None, right]));
+        let (root, left, right) = (positions[0], positions[1], positions[7]);
+        assert_eq!((0.0, 1.0, 1.0), (root.1, left.1, right.1));
+        assert!(right.0 - left.0 > DISTANCE);
+    }
+
+    #[test]
+    fn small_subtrees_are_spaced_evenly() {
+        // The classic case the linear time fix-up handles: small siblings
+        // between two wide ones get spread out evenly
+        let wide = || {
+            n([
+                n([leaf(), leaf(), leaf()]),
+                None,
+                n([leaf(), leaf(), leaf()]),
+            ])
+        };
+        let mut out = vec![Position::default(); 64];
+        let mut tree = Tree::new(n([wide(), leaf(), wide()]));
+        let len = layout(&mut tree, &mut out);
+        check(&out[..len]);
+        let children: Vec<f64> = [1, 10, 11].iter().map(|&i| out[i].x).collect();
+        assert!((children[1] - children[0] - (children[2] - children[1])).abs() < 1e-9);
+    }
+}
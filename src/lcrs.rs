@@ -0,0 +1,138 @@
+use crate::{
+    binary_tree::{Node, NodeIter, Tree, LEFT, RIGHT},
+    tagged_ptr::TaggedPtr,
+};
+
+/// A tree with any number of children per node, stored in binary nodes as
+/// left-child right-sibling: each node's left slot holds its first child and
+/// its right slot its next sibling.
+///
+/// Paths index the logical tree, not the binary one.
+pub struct LcrsTree<T> {
+    tree: Tree<T>,
+}
+
+impl<T> LcrsTree<T> {
+    pub fn new(root: T) -> Self {
+        Self {
+            tree: Tree::new(Some(Node::alloc(root, [None, None]))),
+        }
+    }
+
+    /// The slot that child `i` of `node` lives in, which may be empty.
+    fn child_slot(node: *mut Node<T>, i: usize) -> *mut TaggedPtr<Node<T>> {
+        // SAFETY: Only called on live nodes while no traversal is in progress
+        let mut slot: *mut _ = unsafe { &mut (*node).children[LEFT] };
+        for _ in 0..i {
+            let Some(sibling) = (unsafe { (*slot).as_untagged().as_mut() }) else {
+                break;
+            };
+            slot = &mut sibling.children[RIGHT];
+        }
+        slot
+    }
+
+    fn find(&self, path: &[usize]) -> *mut Node<T> {
+        let mut node = self.tree.root.cast_mut();
+        for &i in path {
+            if node.is_null() {
+                break;
+            }
+            node = unsafe { (*Self::child_slot(node, i)).as_untagged() };
+        }
+        node
+    }
+
+    pub fn get(&self, path: impl AsRef<[usize]>) -> Option<&T> {
+        unsafe { self.find(path.as_ref()).as_ref() }.map(|n| &n.val)
+    }
+
+    pub fn get_mut(&mut self, path: impl AsRef<[usize]>) -> Option<&mut T> {
+        unsafe { self.find(path.as_ref()).as_mut() }.map(|n| &mut n.val)
+    }
+
+    /// How many children the node at `path` has.
+    pub fn arity_at(&self, path: impl AsRef<[usize]>) -> Option<usize> {
+        let node = unsafe { self.find(path.as_ref()).as_ref()? };
+        let mut child = node.children[LEFT].as_untagged();
+        let mut arity = 0;
+        while let Some(cur) = unsafe { child.as_ref() } {
+            arity += 1;
+            child = cur.children[RIGHT].as_untagged();
+        }
+        Some(arity)
+    }
+
+    /// Adds `val` as the last child of the node at `path`, returning its
+    /// index.
+    ///
+    /// Panics if there's no node at `path`.
+    pub fn push_child(&mut self, path: impl AsRef<[usize]>, val: T) -> usize {
+        let path = path.as_ref();
+        let node = self.find(path);
+        assert!(!node.is_null(), "no node at path {path:?}");
+        let arity = self.arity_at(path).unwrap();
+        let child = Box::into_raw(Node::alloc(val, [None, None]));
+        // SAFETY: The slot after the last child is always there to be filled
+        unsafe {
+            let slot = Self::child_slot(node, arity);
+            *slot = (*slot).retarget(child);
+        }
+        arity
+    }
+
+    /// Iterates over the values in preorder, which is the same for the logical
+    /// tree as for the binary one.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.tree.dfs_iter_mut()
+    }
+
+    /// Iterates over the values in post-order. Logically a node is done once
+    /// its first child and that child's siblings are, which is when the binary
+    /// traversal is between a node's two slots.
+    pub fn post_order_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        // SAFETY: The traversal never touches values
+        NodeIter::<T, 1>::new(self.tree.root).map(|node| unsafe { &mut (*node).val })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// a
+    /// ├── b
+    /// │   └── e
+    /// ├── c
+    /// └── d
+    fn sample() -> LcrsTree<char> {
+        let mut tree = LcrsTree::new('a');
+        for ch in ['b', 'c', 'd'] {
+            tree.push_child([], ch);
+        }
+        assert_eq!(0, tree.push_child([0], 'e'));
+        tree
+    }
+
+    #[test]
+    fn paths() {
+        let mut tree = sample();
+        assert_eq!(Some(&'e'), tree.get([0, 0]));
+        assert_eq!(Some(&'d'), tree.get([2]));
+        assert_eq!(None, tree.get([3]));
+        assert_eq!(None, tree.get([1, 0]));
+        assert_eq!(Some(3), tree.arity_at([]));
+        assert_eq!(Some(0), tree.arity_at([2]));
+        *tree.get_mut([1]).unwrap() = 'C';
+        assert_eq!(Some(&'C'), tree.get([1]));
+    }
+
+    #[test]
+    fn logical_order() {
+        let mut tree = sample();
+        let pre: String = tree.iter_mut().map(|c| *c).collect();
+        assert_eq!("abecd", pre);
+        let post: String = tree.post_order_mut().map(|c| *c).collect();
+        assert_eq!("ebcda", post);
+    }
+}
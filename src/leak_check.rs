@@ -0,0 +1,217 @@
+//! Counts allocations so tests can check that trees free exactly what they
+//! allocate, whether they're dropped, iterated partway or panicked out of.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    cmp::Ordering,
+    panic::{self, AssertUnwindSafe},
+};
+
+thread_local! {
+    /// Allocations minus deallocations on this thread, so tests running in
+    /// parallel don't see each other.
+    static LIVE: Cell<isize> = const { Cell::new(0) };
+
+    /// How many more comparisons a [`Touchy`] makes before bailing.
+    static FUSE: Cell<usize> = const { Cell::new(usize::MAX) };
+}
+
+fn count(change: isize) {
+    // Threads that are shutting down can still free things
+    let _ = LIVE.try_with(|live| live.set(live.get() + change));
+}
+
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            count(1);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        count(-1);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Unwinds without running the panic hook, which can allocate things that are
+/// never freed, like backtrace caches.
+pub(crate) fn bail() -> ! {
+    panic::resume_unwind(Box::new("bailing out"))
+}
+
+/// A key that [`bail`]s once it's been compared more times than
+/// [`set_fuse`] allows, for checking search trees that panic mid-rearrange.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Touchy(pub(crate) u32);
+
+impl Ord for Touchy {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let left = FUSE.get();
+        if left == 0 {
+            bail();
+        }
+        FUSE.set(left - 1);
+        self.0.cmp(&other.0)
+    }
+}
+
+impl PartialOrd for Touchy {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Lets [`Touchy`] keys on this thread be compared `n` more times.
+pub(crate) fn set_fuse(n: usize) {
+    FUSE.set(n);
+}
+
+/// Runs `f`, catching any panic, and checks that everything it allocated has
+/// been freed by the time it's done.
+pub(crate) fn assert_no_leaks(f: impl FnOnce()) {
+    // The first panic sets up things like backtrace caches that are never
+    // freed, so get that out of the way first
+    drop(panic::catch_unwind(|| {
+        panic!("warming up the panic machinery")
+    }));
+    let before = LIVE.with(Cell::get);
+    drop(panic::catch_unwind(AssertUnwindSafe(f)));
+    let after = LIVE.with(Cell::get);
+    assert_eq!(before, after, "allocations and frees don't match up");
+}
+
+mod test {
+    use super::*;
+    use crate::{
+        array_tree::{Node, Tree},
+        rewrite::{rewrite, Outcome},
+    };
+
+    fn sample() -> Tree<String, 2> {
+        let leaf = |val: &str| Some(Node::alloc(val.to_owned(), [None, None]));
+        let inner = Node::alloc("b".to_owned(), [leaf("c"), None]);
+        Tree::new(Some(Node::alloc("a".to_owned(), [Some(inner), leaf("d")])))
+    }
+
+    #[test]
+    fn catches_leaks() {
+        let result = panic::catch_unwind(|| assert_no_leaks(|| _ = Box::leak(Box::new(0))));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn drops() {
+        assert_no_leaks(|| drop(sample()));
+        assert_no_leaks(|| sample().drop_with(()));
+        assert_no_leaks(|| sample().drop_each(String::clear));
+        assert_no_leaks(|| drop(sample().clone()));
+    }
+
+    #[test]
+    fn partial_iteration() {
+        assert_no_leaks(|| {
+            let mut tree = sample();
+            tree.dfs_iter_mut().nth(2);
+        });
+        assert_no_leaks(|| {
+            let mut iter = sample().into_iter();
+            iter.nth(1);
+        });
+        assert_no_leaks(|| drop(sample().into_iter().collect::<Vec<_>>()));
+    }
+
+    #[test]
+    fn panics() {
+        assert_no_leaks(|| {
+            let tree = sample();
+            for v in tree.dfs_iter() {
+                if v == "c" {
+                    bail();
+                }
+            }
+        });
+        assert_no_leaks(|| {
+            Tree::<String, 2>::from_fn(3, |path| {
+                if path.len() == 2 {
+                    bail();
+                }
+                Some(format!("{path:?}"))
+            });
+        });
+        assert_no_leaks(|| {
+            for v in sample() {
+                if v == "c" {
+                    bail();
+                }
+            }
+        });
+        assert_no_leaks(|| {
+            let mut tree = sample();
+            tree.sort_children_by_key(|v| {
+                if v == "d" {
+                    bail();
+                }
+                v.clone()
+            });
+        });
+        assert_no_leaks(|| {
+            let mut tree = sample();
+            rewrite(
+                &mut tree,
+                &[|view| match view.value().as_str() {
+                    "c" => {
+                        Outcome::Replace(Tree::new(Some(Node::alloc("e".to_owned(), [None, None]))))
+                    }
+                    "d" => bail(),
+                    _ => Outcome::Unchanged,
+                }],
+            );
+        });
+    }
+
+    #[test]
+    fn construction_fails() {
+        let data: Vec<u8> = (0..64).collect();
+        let (_, values) = Tree::<u8, 3>::arbitrary(&data);
+        // Fail at each value in turn
+        for fail_at in 0..values.len() {
+            assert_no_leaks(|| {
+                let mut decoded = 0;
+                Tree::<String, 3>::arbitrary_with(&data, |data| {
+                    if decoded == fail_at {
+                        bail();
+                    }
+                    decoded += 1;
+                    let (&val, rest) = data.split_first()?;
+                    *data = rest;
+                    Some(val.to_string())
+                });
+            });
+        }
+    }
+
+    #[test]
+    fn failed_conversions() {
+        assert_no_leaks(|| drop(sample().try_into_arity::<1>()));
+        assert_no_leaks(|| {
+            let bytes = [1, b'a', 1, b'b', 1];
+            let decode = |reader: &mut &[u8]| {
+                let (&val, rest) = reader
+                    .split_first()
+                    .ok_or(std::io::ErrorKind::UnexpectedEof)?;
+                *reader = rest;
+                Ok(val)
+            };
+            assert!(Tree::<u8, 2>::decode_from(&mut &bytes[..], decode).is_err());
+        });
+    }
+}
@@ -1,3 +1,26 @@
+pub mod array_tree;
+pub mod augment;
 pub mod binary_tree;
+pub mod checkpoint;
+pub mod decision;
+pub mod edges;
+pub mod eval;
+pub mod huffman;
+pub mod interval;
+pub mod keyed;
+pub mod layout;
+pub mod lcrs;
+#[cfg(test)]
+mod leak_check;
+pub mod observer;
+pub mod octree;
+pub mod path;
+pub mod profile;
+pub mod quadtree;
+pub mod query;
+pub mod rewrite;
+pub mod segment_tree;
+pub mod splay;
 pub mod tagged_ptr;
-pub mod array_tree;
+pub mod treap;
+pub mod trie;
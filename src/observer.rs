@@ -0,0 +1,89 @@
+/// Hooks for watching traversals, e.g. to count visited nodes in production.
+///
+/// Values aren't passed along since the iterator may have lent them out.
+pub trait TraversalObserver {
+    /// A node was stepped into for the first time.
+    fn on_enter(&mut self) {}
+
+    /// A node's children are all done, or were skipped.
+    fn on_leave(&mut self) {}
+
+    /// An iterator dropped partway through put a node's links back.
+    fn on_repair(&mut self) {}
+
+    /// A node was freed.
+    fn on_drop_node(&mut self) {}
+}
+
+impl TraversalObserver for () {}
+
+impl<O: TraversalObserver + ?Sized> TraversalObserver for &mut O {
+    fn on_enter(&mut self) {
+        (**self).on_enter();
+    }
+
+    fn on_leave(&mut self) {
+        (**self).on_leave();
+    }
+
+    fn on_repair(&mut self) {
+        (**self).on_repair();
+    }
+
+    fn on_drop_node(&mut self) {
+        (**self).on_drop_node();
+    }
+}
+
+/// Tallies up every event.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counts {
+    pub entered: usize,
+    pub left: usize,
+    pub repaired: usize,
+    pub dropped: usize,
+}
+
+impl TraversalObserver for Counts {
+    fn on_enter(&mut self) {
+        self.entered += 1;
+    }
+
+    fn on_leave(&mut self) {
+        self.left += 1;
+    }
+
+    fn on_repair(&mut self) {
+        self.repaired += 1;
+    }
+
+    fn on_drop_node(&mut self) {
+        self.dropped += 1;
+    }
+}
+
+/// Tracks how deep a traversal has gone, counting the root as the first level.
+/// That's as deep as a recursive walk's stack would have gotten.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MaxDepth {
+    depth: usize,
+    max: usize,
+}
+
+impl MaxDepth {
+    /// The most levels that were ever entered at once.
+    pub fn max_depth_seen(&self) -> usize {
+        self.max
+    }
+}
+
+impl TraversalObserver for MaxDepth {
+    fn on_enter(&mut self) {
+        self.depth += 1;
+        self.max = self.max.max(self.depth);
+    }
+
+    fn on_leave(&mut self) {
+        self.depth -= 1;
+    }
+}
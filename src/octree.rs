@@ -0,0 +1,284 @@
+use crate::{
+    array_tree::{Node, NodeIter, Tree},
+    tagged_ptr::TaggedPtr,
+};
+
+/// Integer voxel coordinates.
+pub type Pos = [u32; 3];
+
+/// The half-space of points `p` with `normal · p + offset >= 0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: [f32; 3],
+    pub offset: f32,
+}
+
+#[derive(Debug)]
+struct Octant<V> {
+    origin: Pos,
+    size: u32,
+    voxel: Option<V>,
+}
+
+impl<V> Octant<V> {
+    fn child_index(&self, pos: Pos) -> usize {
+        let half = self.size / 2;
+        (0..3)
+            .map(|axis| ((pos[axis] - self.origin[axis] >= half) as usize) << axis)
+            .sum()
+    }
+
+    fn child(&self, index: usize) -> Octant<V> {
+        let half = self.size / 2;
+        Octant {
+            origin: std::array::from_fn(|axis| {
+                self.origin[axis] + half * ((index >> axis) & 1) as u32
+            }),
+            size: half,
+            voxel: None,
+        }
+    }
+
+    fn overlaps(&self, min: Pos, max: Pos) -> bool {
+        (0..3)
+            .all(|axis| self.origin[axis] <= max[axis] && min[axis] < self.origin[axis] + self.size)
+    }
+
+    fn inside(&self, plane: &Plane) -> bool {
+        // Check the corner furthest along the normal
+        let furthest: f32 = (0..3)
+            .map(|axis| {
+                let n = plane.normal[axis];
+                let lo = self.origin[axis] as f32;
+                n * if n >= 0.0 { lo + self.size as f32 } else { lo }
+            })
+            .sum();
+        furthest + plane.offset >= 0.0
+    }
+}
+
+/// An octant as seen by [`Octree::stream`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OctantRecord<'a, V> {
+    pub origin: Pos,
+    pub size: u32,
+    /// Bit `i` is set when child `i` exists. Child `i` covers the upper half of
+    /// axis `a` exactly when bit `a` of `i` is set.
+    pub child_mask: u8,
+    /// Only ever set on unit octants.
+    pub voxel: Option<&'a V>,
+}
+
+/// A sparse voxel octree over the cube `[0, 2^depth)³`.
+pub struct Octree<V> {
+    tree: Tree<Octant<V>, 8>,
+}
+
+impl<V> Octree<V> {
+    /// An empty octree `2^depth` voxels across.
+    ///
+    /// Panics if `depth` is 32 or more, since coordinates are `u32`s.
+    pub fn new(depth: u32) -> Self {
+        let size = 1u32
+            .checked_shl(depth)
+            .expect("octree depth must be less than 32");
+        let root = Octant {
+            origin: [0; 3],
+            size,
+            voxel: None,
+        };
+        Self {
+            tree: Tree::new(Some(Node::alloc(root, [const { None }; 8]))),
+        }
+    }
+
+    fn root(&self) -> &Octant<V> {
+        // SAFETY: The root always exists and no traversal is in progress
+        unsafe { &(*self.tree.root).val }
+    }
+
+    fn in_bounds(&self, pos: Pos) -> bool {
+        pos.iter().all(|&p| p < self.root().size)
+    }
+
+    /// Stores a voxel, returning the one that was there.
+    ///
+    /// Panics if `pos` is outside the octree.
+    pub fn insert(&mut self, pos: Pos, value: V) -> Option<V> {
+        assert!(self.in_bounds(pos), "{pos:?} is outside the octree");
+        // SAFETY: No traversal is in progress since we hold `&mut self`
+        let mut node = unsafe { &mut *self.tree.root.cast_mut() };
+        while node.val.size > 1 {
+            let index = node.val.child_index(pos);
+            if node.children[index].as_untagged().is_null() {
+                let child = Node::alloc(node.val.child(index), [const { None }; 8]);
+                node.children[index] = TaggedPtr::from_untagged(Box::leak(child));
+            }
+            node = unsafe { &mut *node.children[index].as_untagged() };
+        }
+        node.val.voxel.replace(value)
+    }
+
+    pub fn get(&self, pos: Pos) -> Option<&V> {
+        if !self.in_bounds(pos) {
+            return None;
+        }
+        // SAFETY: No traversal is in progress since we hold `&self`
+        let mut node = unsafe { &*self.tree.root };
+        while node.val.size > 1 {
+            let index = node.val.child_index(pos);
+            node = unsafe { node.children[index].as_untagged().as_ref()? };
+        }
+        node.val.voxel.as_ref()
+    }
+
+    /// Walks the octants in preorder, only descending where `f` returns true.
+    fn walk(&mut self, mut f: impl FnMut(&mut Octant<V>, u8) -> bool) {
+        let mut iter = NodeIter::<_, 8, 0>::new(self.tree.root);
+        while let Some((node, visited)) = iter.step() {
+            if visited != 0 {
+                continue;
+            }
+            let first_child = iter.first_child(node);
+            // SAFETY: We've only just stepped into this node
+            let node_ref = unsafe { &mut *node };
+            let child_mask = node_ref
+                .children
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    let child = if i == 0 { first_child } else { c.as_untagged() };
+                    (!child.is_null() as u8) << i
+                })
+                .sum();
+            if !f(&mut node_ref.val, child_mask) {
+                iter.skip_children(node);
+            }
+        }
+    }
+
+    /// Calls `f` for every voxel in the inclusive box from `min` to `max`.
+    pub fn query_box(&mut self, min: Pos, max: Pos, mut f: impl FnMut(Pos, &mut V)) {
+        self.walk(|octant, _| {
+            if !octant.overlaps(min, max) {
+                return false;
+            }
+            if let Some(voxel) = &mut octant.voxel {
+                f(octant.origin, voxel);
+            }
+            true
+        });
+    }
+
+    /// Calls `f` for every voxel touching the volume bounded by `planes`, such
+    /// as a view frustum. Octants outside any one plane are skipped entirely.
+    pub fn query_frustum(&mut self, planes: &[Plane], mut f: impl FnMut(Pos, &mut V)) {
+        self.walk(|octant, _| {
+            if !planes.iter().all(|plane| octant.inside(plane)) {
+                return false;
+            }
+            if let Some(voxel) = &mut octant.voxel {
+                f(octant.origin, voxel);
+            }
+            true
+        });
+    }
+
+    /// Streams every octant out in preorder, e.g. to fill a GPU buffer in the
+    /// usual child-mask layout.
+    pub fn stream(&mut self, mut f: impl FnMut(OctantRecord<'_, V>)) {
+        self.walk(|octant, child_mask| {
+            f(OctantRecord {
+                origin: octant.origin,
+                size: octant.size,
+                child_mask,
+                voxel: octant.voxel.as_ref(),
+            });
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample() -> Octree<u32> {
+        let mut tree = Octree::new(3);
+        for (i, pos) in [[0, 0, 0], [7, 7, 7], [1, 2, 3], [4, 0, 0], [1, 2, 2]]
+            .into_iter()
+            .enumerate()
+        {
+            assert_eq!(None, tree.insert(pos, i as u32));
+        }
+        tree
+    }
+
+    #[test]
+    fn insert_get() {
+        let mut tree = sample();
+        assert_eq!(Some(&2), tree.get([1, 2, 3]));
+        assert_eq!(None, tree.get([1, 2, 4]));
+        assert_eq!(None, tree.get([8, 0, 0]));
+        assert_eq!(Some(2), tree.insert([1, 2, 3], 9));
+        assert_eq!(Some(&9), tree.get([1, 2, 3]));
+    }
+
+    #[test]
+    fn deepest() {
+        let mut tree = Octree::new(31);
+        let far = [u32::MAX >> 1; 3];
+        assert_eq!(None, tree.insert(far, 1));
+        assert_eq!(Some(&1), tree.get(far));
+    }
+
+    #[test]
+    #[should_panic = "octree depth must be less than 32"]
+    fn too_deep() {
+        let _ = Octree::<u32>::new(32);
+    }
+
+    #[test]
+    fn query_box() {
+        let mut tree = sample();
+        let mut found = Vec::new();
+        tree.query_box([0, 0, 0], [4, 2, 2], |pos, v| found.push((pos, *v)));
+        assert_eq!(vec![([0, 0, 0], 0), ([1, 2, 2], 4), ([4, 0, 0], 3)], found);
+    }
+
+    #[test]
+    fn query_frustum() {
+        let mut tree = sample();
+        let mut found = Vec::new();
+        // x <= 1.5 and z >= 2.5
+        let planes = [
+            Plane {
+                normal: [-1.0, 0.0, 0.0],
+                offset: 1.5,
+            },
+            Plane {
+                normal: [0.0, 0.0, 1.0],
+                offset: -2.5,
+            },
+        ];
+        tree.query_frustum(&planes, |pos, v| found.push((pos, *v)));
+        assert_eq!(vec![([1, 2, 2], 4), ([1, 2, 3], 2)], found);
+    }
+
+    #[test]
+    fn stream() {
+        let mut tree = Octree::new(1);
+        tree.insert([1, 0, 0], 'a');
+        tree.insert([0, 0, 1], 'b');
+        let mut records = Vec::new();
+        tree.stream(|r| records.push((r.origin, r.size, r.child_mask, r.voxel.copied())));
+        assert_eq!(
+            vec![
+                ([0, 0, 0], 2, 0b10010, None),
+                ([1, 0, 0], 1, 0, Some('a')),
+                ([0, 0, 1], 1, 0, Some('b')),
+            ],
+            records
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    error::Error,
+    fmt::{self, Debug, Display},
+    ops::Deref,
+    str::FromStr,
+};
+
+/// How many indices fit before spilling onto the heap.
+const INLINE: usize = 8;
+
+/// A path of child indices from the root down to a node, e.g. `1/0/2` for the
+/// third child of the first child of the second child of the root. The empty
+/// path is the root itself.
+///
+/// Short paths are stored inline. Anything taking a path takes
+/// `impl AsRef<[usize]>`, so plain slices and arrays work too.
+#[derive(Clone)]
+pub struct TreePath {
+    len: usize,
+    inline: [usize; INLINE],
+    /// Holds the whole path once it's outgrown `inline`
+    spilled: Vec<usize>,
+}
+
+impl TreePath {
+    /// The path to the root.
+    pub const fn root() -> Self {
+        Self {
+            len: 0,
+            inline: [0; INLINE],
+            spilled: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, index: usize) {
+        if self.len < INLINE {
+            self.inline[self.len] = index;
+        } else {
+            if self.len == INLINE {
+                self.spilled.extend_from_slice(&self.inline);
+            }
+            self.spilled.push(index);
+        }
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<usize> {
+        let last = *self.last()?;
+        if self.len > INLINE {
+            self.spilled.pop();
+            if self.len == INLINE + 1 {
+                self.spilled.clear();
+            }
+        }
+        self.len -= 1;
+        Some(last)
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.spilled.clear();
+    }
+
+    /// The path to the child `index` below this one.
+    pub fn child(mut self, index: usize) -> Self {
+        self.push(index);
+        self
+    }
+
+    /// This path followed by `rest`, which is relative to where this one ends.
+    pub fn join(&self, rest: impl AsRef<[usize]>) -> Self {
+        let mut path = self.clone();
+        path.extend(rest.as_ref().iter().copied());
+        path
+    }
+
+    /// Whether this path leads to `other` or one of its ancestors.
+    pub fn is_prefix_of(&self, other: impl AsRef<[usize]>) -> bool {
+        other.as_ref().starts_with(self)
+    }
+
+    /// Parses indices separated by `/`. The empty string is the root.
+    pub fn parse(s: &str) -> Result<Self, ParsePathError> {
+        s.parse()
+    }
+}
+
+impl Default for TreePath {
+    fn default() -> Self {
+        Self::root()
+    }
+}
+
+impl Deref for TreePath {
+    type Target = [usize];
+
+    fn deref(&self) -> &[usize] {
+        if self.len > INLINE {
+            &self.spilled
+        } else {
+            &self.inline[..self.len]
+        }
+    }
+}
+
+impl AsRef<[usize]> for TreePath {
+    fn as_ref(&self) -> &[usize] {
+        self
+    }
+}
+
+impl PartialEq for TreePath {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for TreePath {}
+
+impl Extend<usize> for TreePath {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        for index in iter {
+            self.push(index);
+        }
+    }
+}
+
+impl FromIterator<usize> for TreePath {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut path = Self::root();
+        path.extend(iter);
+        path
+    }
+}
+
+impl From<&[usize]> for TreePath {
+    fn from(indices: &[usize]) -> Self {
+        indices.iter().copied().collect()
+    }
+}
+
+impl Debug for TreePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TreePath({self})")
+    }
+}
+
+/// Writes the path the way [`TreePath::parse`] reads it.
+impl Display for TreePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, index) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str("/")?;
+            }
+            write!(f, "{index}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TreePath {
+    type Err = ParsePathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(Self::root());
+        }
+        s.split('/')
+            .map(|part| {
+                part.parse().map_err(|_| ParsePathError {
+                    part: part.to_owned(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// A part of a path that wasn't an index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePathError {
+    part: String,
+}
+
+impl Display for ParsePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid child index {:?} in path", self.part)
+    }
+}
+
+impl Error for ParsePathError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        let path = TreePath::parse("1/0/2").unwrap();
+        assert_eq!(&[1, 0, 2], &*path);
+        assert_eq!("1/0/2", path.to_string());
+        assert_eq!(TreePath::root(), TreePath::parse("").unwrap());
+        assert_eq!(
+            "invalid child index \"x\" in path",
+            TreePath::parse("1/x").unwrap_err().to_string()
+        );
+        assert!(TreePath::parse("1//2").is_err());
+    }
+
+    #[test]
+    fn spills() {
+        let mut path: TreePath = (0..20).collect();
+        assert_eq!((0..20).collect::<Vec<_>>(), &*path);
+        while path.len() > 3 {
+            path.pop();
+        }
+        assert_eq!(&[0, 1, 2], &*path);
+        assert_eq!(TreePath::from(&[0, 1, 2][..]), path);
+    }
+
+    #[test]
+    fn join_and_prefix() {
+        let parent = TreePath::root().child(1);
+        let path = parent.join([0, 2]);
+        assert_eq!(&[1, 0, 2], &*path);
+        assert!(parent.is_prefix_of(&path));
+        assert!(TreePath::root().is_prefix_of([3]));
+        assert!(!path.is_prefix_of(&parent));
+    }
+}
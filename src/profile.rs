@@ -0,0 +1,97 @@
+use std::{cell::Cell, cmp::Reverse};
+
+use crate::array_tree::Tree;
+
+/// A value along with how many lookups have gone through it, for moving hot
+/// children to the front with [`Tree::hot_first`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counted<T> {
+    pub val: T,
+    hits: Cell<u64>,
+}
+
+impl<T> Counted<T> {
+    pub fn new(val: T) -> Self {
+        Self {
+            val,
+            hits: Cell::new(0),
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.get()
+    }
+
+    fn hit(&self) {
+        self.hits.set(self.hits.get() + 1);
+    }
+}
+
+impl<T, const N: usize> Tree<Counted<T>, N> {
+    /// Like [`Tree::get`], but counts a hit on every node passed through,
+    /// including the one found.
+    pub fn get_counted(&self, path: impl AsRef<[usize]>) -> Option<&T> {
+        let path = path.as_ref();
+        // Misses don't count
+        let found = self.get(path)?;
+        let mut node = self.root_node()?;
+        node.value().hit();
+        for &i in path {
+            node = node.child(i)?;
+            node.value().hit();
+        }
+        Some(&found.val)
+    }
+
+    /// Reorders every node's children so the most hit come first. Ties keep
+    /// their order and empty slots go last.
+    ///
+    /// This moves nodes between slots, so paths recorded beforehand will lead
+    /// elsewhere afterwards.
+    pub fn hot_first(&mut self) {
+        self.sort_children_by_key(|counted| Reverse(counted.hits()));
+    }
+
+    /// Forgets all hits, e.g. to start profiling a new workload.
+    pub fn reset_hits(&mut self) {
+        for counted in self.dfs_iter_mut() {
+            counted.hits.set(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::array_tree::Node;
+
+    type Child = Option<Box<Node<Counted<char>, 3>>>;
+
+    fn node(val: char, children: [Child; 3]) -> Child {
+        Some(Node::alloc(Counted::new(val), children))
+    }
+
+    fn leaf(val: char) -> Child {
+        node(val, [None, None, None])
+    }
+
+    #[test]
+    fn hot_first() {
+        let mut tree = Tree::new(node(
+            'a',
+            [leaf('b'), None, node('c', [leaf('d'), leaf('e'), None])],
+        ));
+        assert_eq!(Some(&'e'), tree.get_counted([2, 1]));
+        assert_eq!(Some(&'e'), tree.get_counted([2, 1]));
+        assert_eq!(Some(&'b'), tree.get_counted([0]));
+        assert_eq!(None, tree.get_counted([1]));
+        assert_eq!(Some(3), tree.root().map(Counted::hits));
+
+        tree.hot_first();
+        let order: String = tree.dfs_iter().map(|counted| counted.val).collect();
+        assert_eq!("acedb", order);
+        assert_eq!(Some(&'e'), tree.get([0, 0]).map(|counted| &counted.val));
+        tree.reset_hits();
+        assert!(tree.dfs_iter().all(|counted| counted.hits() == 0));
+    }
+}
@@ -0,0 +1,218 @@
+use std::ptr;
+
+use crate::{
+    array_tree::{self, Node, NodeIter, Tree},
+    tagged_ptr::TaggedPtr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// An axis-aligned bounding box, inclusive on all sides.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    pub fn contains(&self, p: Point) -> bool {
+        self.min.x <= p.x && p.x <= self.max.x && self.min.y <= p.y && p.y <= self.max.y
+    }
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    point: Point,
+    value: V,
+}
+
+/// A point quadtree. Every node holds a point and splits the plane around it
+/// into four quadrants, one per child.
+pub struct QuadTree<V> {
+    tree: Tree<Entry<V>, 4>,
+}
+
+/// Which of `center`'s quadrants `p` falls into. Bit 0 is set for the east
+/// half and bit 1 for the north half.
+fn quadrant(center: Point, p: Point) -> usize {
+    (p.x >= center.x) as usize | ((p.y >= center.y) as usize) << 1
+}
+
+/// The squared distance from `p` to the closest point that could be stored in
+/// `center`'s given quadrant.
+fn quadrant_dist2(center: Point, quadrant: usize, p: Point) -> f64 {
+    let dx = if quadrant & 1 == 0 {
+        p.x - center.x
+    } else {
+        center.x - p.x
+    };
+    let dy = if quadrant & 2 == 0 {
+        p.y - center.y
+    } else {
+        center.y - p.y
+    };
+    dx.max(0.0).powi(2) + dy.max(0.0).powi(2)
+}
+
+fn dist2(a: Point, b: Point) -> f64 {
+    (a.x - b.x).powi(2) + (a.y - b.y).powi(2)
+}
+
+impl<V> Default for QuadTree<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> QuadTree<V> {
+    pub fn new() -> Self {
+        Self {
+            tree: Tree::empty(),
+        }
+    }
+
+    pub fn insert(&mut self, point: Point, value: V) {
+        let new = Box::leak(Node::alloc(Entry { point, value }, [const { None }; 4]));
+        // SAFETY: No traversal is in progress since we hold `&mut self`
+        let Some(mut node) = (unsafe { self.tree.root.cast_mut().as_mut() }) else {
+            self.tree.root = new;
+            return;
+        };
+        loop {
+            let slot = &mut node.children[quadrant(node.val.point, point)];
+            match unsafe { slot.as_untagged().as_mut() } {
+                Some(child) => node = child,
+                None => {
+                    *slot = TaggedPtr::from_untagged(new);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Walks the tree in preorder. `f` gets each entry along with the point
+    /// of its parent and the quadrant of the parent it's in, and returns
+    /// whether to descend into the entry's children.
+    fn walk(&mut self, mut f: impl FnMut(Option<(Point, usize)>, &mut Entry<V>) -> bool) {
+        let mut iter = NodeIter::<_, 4, 0>::new(self.tree.root);
+        while let Some((node, visited)) = iter.step() {
+            if visited != 0 {
+                continue;
+            }
+            // SAFETY: We've only just stepped into this node
+            let parent = unsafe { array_tree::ancestors(node) }
+                .next()
+                .map(|(parent, quadrant)| (unsafe { (*parent).val.point }, quadrant));
+            if !f(parent, unsafe { &mut (*node).val }) {
+                iter.skip_children(node);
+            }
+        }
+    }
+
+    /// Calls `f` with every point inside `region`.
+    pub fn query(&mut self, region: Aabb, mut f: impl FnMut(Point, &mut V)) {
+        self.walk(|parent, entry| {
+            if let Some((center, quadrant)) = parent {
+                // Is the region entirely on the other side of the parent?
+                let east = quadrant & 1 != 0;
+                let north = quadrant & 2 != 0;
+                if (east && region.max.x < center.x)
+                    || (!east && region.min.x >= center.x)
+                    || (north && region.max.y < center.y)
+                    || (!north && region.min.y >= center.y)
+                {
+                    return false;
+                }
+            }
+            if region.contains(entry.point) {
+                f(entry.point, &mut entry.value);
+            }
+            true
+        });
+    }
+
+    /// Finds the closest point to `target`.
+    pub fn nearest(&mut self, target: Point) -> Option<(Point, &mut V)> {
+        let mut best: *mut Entry<V> = ptr::null_mut();
+        let mut best_dist2 = f64::INFINITY;
+        self.walk(|parent, entry| {
+            if let Some((center, quadrant)) = parent {
+                if quadrant_dist2(center, quadrant, target) >= best_dist2 {
+                    return false;
+                }
+            }
+            let d = dist2(entry.point, target);
+            if d < best_dist2 {
+                best = entry;
+                best_dist2 = d;
+            }
+            true
+        });
+        // SAFETY: The walk has finished so nothing else is pointing into the
+        // tree
+        let entry = unsafe { best.as_mut()? };
+        Some((entry.point, &mut entry.value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn p(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    fn grid() -> QuadTree<(i32, i32)> {
+        let mut tree = QuadTree::new();
+        // Insert in a scrambled order so the tree isn't degenerate
+        for i in [3, 0, 6, 1, 5, 2, 4] {
+            for j in [4, 1, 6, 0, 3, 5, 2] {
+                tree.insert(p(i as f64, j as f64), (i, j));
+            }
+        }
+        tree
+    }
+
+    #[test]
+    fn query() {
+        let mut tree = grid();
+        let mut found = Vec::new();
+        let region = Aabb {
+            min: p(1.0, 2.0),
+            max: p(2.5, 4.0),
+        };
+        tree.query(region, |_, v| found.push(*v));
+        found.sort();
+        assert_eq!(vec![(1, 2), (1, 3), (1, 4), (2, 2), (2, 3), (2, 4)], found);
+
+        let mut count = 0;
+        let everything = Aabb {
+            min: p(-10.0, -10.0),
+            max: p(10.0, 10.0),
+        };
+        tree.query(everything, |_, _| count += 1);
+        assert_eq!(49, count);
+    }
+
+    #[test]
+    fn nearest() {
+        let mut tree = grid();
+        assert_eq!(Some((p(2.0, 5.0), &mut (2, 5))), tree.nearest(p(2.2, 4.9)));
+        assert_eq!(
+            Some((p(6.0, 0.0), &mut (6, 0))),
+            tree.nearest(p(100.0, -3.0))
+        );
+        let (_, v) = tree.nearest(p(0.0, 0.0)).unwrap();
+        *v = (-1, -1);
+        assert_eq!(
+            Some((p(0.0, 0.0), &mut (-1, -1))),
+            tree.nearest(p(0.1, 0.1))
+        );
+        assert!(QuadTree::<()>::new().nearest(p(0.0, 0.0)).is_none());
+    }
+}
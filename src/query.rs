@@ -0,0 +1,226 @@
+use crate::array_tree::{self, NodeIter, Tree};
+
+enum Step<T> {
+    Child(usize),
+    Children,
+    Descendants,
+    Filter(Box<dyn Fn(&T) -> bool>),
+}
+
+/// A path expression over a tree, in the spirit of XPath.
+///
+/// ```rust
+/// use constant_size_dfs::{array_tree::{Node, Tree}, query::Query};
+/// let mut tree = Tree::new(Some(Node::alloc(1, [Some(Node::alloc(2, [None, None])), None])));
+/// let mut found = Vec::new();
+/// Query::root().descendants().filter(|v| v % 2 == 0).for_each(&mut tree, |v| found.push(*v));
+/// assert_eq!(vec![2], found);
+/// ```
+///
+/// Matching is done by checking each node's ancestry from the bottom up, so
+/// running a query takes O(n·depth) time. [`Query::for_each`] needs no memory
+/// beyond the query itself, while [`Query::for_each_path`] also holds the path
+/// to the current match, which takes O(depth).
+pub struct Query<T> {
+    steps: Vec<Step<T>>,
+}
+
+/// Matching states are tracked as a bitmask over positions in the query
+const MAX_STEPS: usize = u64::BITS as usize - 1;
+
+impl<T> Query<T> {
+    /// Matches the root.
+    pub fn root() -> Self {
+        Self { steps: Vec::new() }
+    }
+
+    /// Moves to child `i` of each match.
+    pub fn child(self, i: usize) -> Self {
+        self.push(Step::Child(i))
+    }
+
+    /// Moves to every child of each match.
+    pub fn children(self) -> Self {
+        self.push(Step::Children)
+    }
+
+    /// Moves to every descendant of each match, excluding the match itself.
+    pub fn descendants(self) -> Self {
+        self.push(Step::Descendants)
+    }
+
+    /// Keeps only the matches whose value satisfies `pred`.
+    pub fn filter(self, pred: impl Fn(&T) -> bool + 'static) -> Self {
+        self.push(Step::Filter(Box::new(pred)))
+    }
+
+    fn push(mut self, step: Step<T>) -> Self {
+        assert!(self.steps.len() < MAX_STEPS, "query is too long");
+        self.steps.push(step);
+        self
+    }
+
+    /// Calls `f` on every matching value in preorder.
+    pub fn for_each<const N: usize>(&self, tree: &mut Tree<T, N>, mut f: impl FnMut(&mut T)) {
+        // SAFETY: Traversals never touch values
+        self.for_each_node(tree, |node| f(unsafe { &mut (*node).val }));
+    }
+
+    /// Calls `f` on every matching value in preorder, along with the child
+    /// indices leading to it from the root. The path is kept in a buffer that
+    /// grows to the depth of the deepest match.
+    pub fn for_each_path<const N: usize>(
+        &self,
+        tree: &mut Tree<T, N>,
+        mut f: impl FnMut(&[usize], &mut T),
+    ) {
+        let mut path = Vec::new();
+        self.for_each_node(tree, |node| {
+            path.clear();
+            // SAFETY: We've only just stepped into this node
+            path.extend(unsafe { array_tree::ancestors(node) }.map(|(_, i)| i));
+            path.reverse();
+            f(&path, unsafe { &mut (*node).val });
+        });
+    }
+
+    /// Calls `f` on every matching node in preorder, just after stepping into
+    /// it.
+    fn for_each_node<const N: usize>(
+        &self,
+        tree: &mut Tree<T, N>,
+        mut f: impl FnMut(*mut array_tree::Node<T, N>),
+    ) {
+        let mut iter = NodeIter::<T, N, 0>::new(tree.root);
+        while let Some((node, visited)) = iter.step() {
+            if visited == 0 && self.matches(node) {
+                f(node);
+            }
+        }
+    }
+
+    /// Runs the query backwards from `node` up to the root. Bit `j` of the
+    /// state means the steps from `j` onwards match the path below the node
+    /// we're currently at.
+    fn matches<const N: usize>(&self, node: *mut array_tree::Node<T, N>) -> bool {
+        let end = self.steps.len();
+        // SAFETY: We've only just stepped into this node
+        let mut states = self.apply_filters(1 << end, unsafe { &(*node).val });
+        for (parent, edge) in unsafe { array_tree::ancestors(node) } {
+            let mut next = 0;
+            for (j, step) in self.steps.iter().enumerate() {
+                let after = states & (1 << (j + 1)) != 0;
+                let at = states & (1 << j) != 0;
+                next |= match step {
+                    Step::Child(i) if after && *i == edge => 1 << j,
+                    Step::Children if after => 1 << j,
+                    // Both the edge that starts the run and any edge above it
+                    Step::Descendants if after || at => 1 << j,
+                    _ => 0,
+                };
+            }
+            states = self.apply_filters(next, unsafe { &(*parent).val });
+            if states == 0 {
+                return false;
+            }
+        }
+        states & 1 != 0
+    }
+
+    fn apply_filters(&self, mut states: u64, val: &T) -> u64 {
+        for (j, step) in self.steps.iter().enumerate().rev() {
+            if let Step::Filter(pred) = step {
+                if states & (1 << (j + 1)) != 0 && pred(val) {
+                    states |= 1 << j;
+                }
+            }
+        }
+        states
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::array_tree::Node;
+
+    fn sample() -> Tree<i32, 2> {
+        //       0
+        //    1     4
+        //  2   3     5
+        Tree::new(Some(Node::alloc(
+            0,
+            [
+                Some(Node::alloc(
+                    1,
+                    [
+                        Some(Node::alloc(2, [None, None])),
+                        Some(Node::alloc(3, [None, None])),
+                    ],
+                )),
+                Some(Node::alloc(4, [None, Some(Node::alloc(5, [None, None]))])),
+            ],
+        )))
+    }
+
+    fn paths(query: Query<i32>) -> Vec<(Vec<usize>, i32)> {
+        let mut tree = sample();
+        let mut found = Vec::new();
+        query.for_each_path(&mut tree, |path, val| found.push((path.to_vec(), *val)));
+        // Querying must leave the tree intact
+        let all: Vec<_> = tree.dfs_iter_mut().map(|v| *v).collect();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], all);
+        found
+    }
+
+    #[test]
+    fn root() {
+        assert_eq!(vec![(vec![], 0)], paths(Query::root()));
+    }
+
+    #[test]
+    fn child_chain() {
+        assert_eq!(
+            vec![(vec![0, 1], 3)],
+            paths(Query::root().child(0).child(1))
+        );
+        assert_eq!(
+            Vec::<(Vec<usize>, i32)>::new(),
+            paths(Query::root().child(1).child(0))
+        );
+    }
+
+    #[test]
+    fn children() {
+        let found = paths(Query::root().children().children());
+        assert_eq!(
+            vec![(vec![0, 0], 2), (vec![0, 1], 3), (vec![1, 1], 5)],
+            found
+        );
+    }
+
+    #[test]
+    fn descendants() {
+        let found: Vec<_> = paths(Query::root().descendants())
+            .into_iter()
+            .map(|(_, v)| v)
+            .collect();
+        assert_eq!(vec![1, 2, 3, 4, 5], found);
+        let found = paths(Query::root().descendants().child(1));
+        assert_eq!(vec![(vec![0, 1], 3), (vec![1, 1], 5)], found);
+    }
+
+    #[test]
+    fn filters() {
+        let found = paths(Query::root().descendants().filter(|v| v % 2 == 1).child(1));
+        assert_eq!(vec![(vec![0, 1], 3)], found);
+        let found = paths(
+            Query::root()
+                .filter(|v| *v == 0)
+                .children()
+                .filter(|v| *v > 3)
+                .descendants(),
+        );
+        assert_eq!(vec![(vec![1, 1], 5)], found);
+    }
+}
@@ -0,0 +1,202 @@
+use std::ptr;
+
+use crate::array_tree::{Node, NodeIter, Tree};
+
+/// A rewrite rule, reporting what it did to the viewed node.
+pub type Rule<T, const N: usize> = fn(&mut NodeView<'_, T, N>) -> Outcome<T, N>;
+
+/// What a [`Rule`] did to the node it was handed.
+pub enum Outcome<T, const N: usize> {
+    /// The rule didn't apply, so the next one is tried.
+    Unchanged,
+    /// The rule changed the node in place, e.g. through
+    /// [`NodeView::value_mut`] or [`NodeView::graft`].
+    Changed,
+    /// The node (and everything below it) should be replaced with this tree.
+    Replace(Tree<T, N>),
+}
+
+/// A node handed to a [`Rule`]. All of its children have already been
+/// rewritten and are fully attached, so they're free to be inspected, detached
+/// or replaced.
+pub struct NodeView<'a, T, const N: usize> {
+    node: &'a mut Node<T, N>,
+}
+
+impl<'a, T, const N: usize> NodeView<'a, T, N> {
+    pub fn value(&self) -> &T {
+        &self.node.val
+    }
+
+    /// The node's value, to change in place. It's up to the rule to report
+    /// [`Outcome::Changed`] if it did.
+    pub fn value_mut(&mut self) -> &mut T {
+        &mut self.node.val
+    }
+
+    pub fn child(&self, i: usize) -> Option<&T> {
+        let child = unsafe { self.node.children[i].as_untagged().as_ref()? };
+        Some(&child.val)
+    }
+
+    /// Takes child `i` out of the tree, leaving the slot empty.
+    pub fn detach(&mut self, i: usize) -> Tree<T, N> {
+        self.graft(i, Tree::empty())
+    }
+
+    /// Puts `subtree` into child slot `i`, returning whatever was there. The
+    /// slot keeps its tag.
+    pub fn graft(&mut self, i: usize, subtree: Tree<T, N>) -> Tree<T, N> {
+        let slot = &mut self.node.children[i];
+        let old = slot.as_untagged();
+        *slot = slot.retarget(subtree.into_raw());
+        Tree::from_raw(old)
+    }
+}
+
+/// Applies `rules` bottom-up until none of them change anything, returning how
+/// many times a rule fired.
+///
+/// Each pass visits the tree in post-order and tries the rules in order on
+/// every node, stopping at the first that doesn't report
+/// [`Outcome::Unchanged`]. A replacement is
+/// not revisited until the next pass. Rules that keep changing the tree will
+/// loop forever.
+pub fn rewrite<T, const N: usize>(tree: &mut Tree<T, N>, rules: &[Rule<T, N>]) -> usize {
+    let mut fired = 0;
+    loop {
+        let fired_this_pass = rewrite_pass(tree, rules);
+        if fired_this_pass == 0 {
+            return fired;
+        }
+        fired += fired_this_pass;
+    }
+}
+
+fn rewrite_pass<T, const N: usize>(tree: &mut Tree<T, N>, rules: &[Rule<T, N>]) -> usize {
+    let mut fired = 0;
+    // The root stays in `tree` so that if a rule panics, the traversal puts
+    // the links back and the tree is still whole to be dropped
+    let mut iter = NodeIter::<T, N, N>::new(tree.root);
+    while let Some((node, visited)) = iter.step() {
+        if visited != N {
+            continue;
+        }
+        // SAFETY: The node has just been finished, so it and its children are
+        // in their original shape.
+        let mut view = NodeView {
+            node: unsafe { &mut *node },
+        };
+        let outcome = rules
+            .iter()
+            .map(|rule| rule(&mut view))
+            .find(|outcome| !matches!(outcome, Outcome::Unchanged));
+        match outcome {
+            None | Some(Outcome::Unchanged) => continue,
+            Some(Outcome::Changed) => {}
+            Some(Outcome::Replace(replacement)) => {
+                let new = replacement.into_raw();
+                let old = iter.replace_finished(new);
+                if ptr::eq(old, tree.root) {
+                    tree.root = new;
+                }
+                drop(Tree::from_raw(old));
+            }
+        }
+        fired += 1;
+    }
+    debug_assert!(ptr::eq(tree.root, iter.finished_root()));
+    fired
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Expr {
+        Num(i64),
+        Add,
+        Mul,
+    }
+
+    fn num(n: i64) -> Option<Box<Node<Expr, 2>>> {
+        Some(Node::alloc(Expr::Num(n), [None, None]))
+    }
+
+    fn op(
+        op: Expr,
+        lhs: Option<Box<Node<Expr, 2>>>,
+        rhs: Option<Box<Node<Expr, 2>>>,
+    ) -> Option<Box<Node<Expr, 2>>> {
+        Some(Node::alloc(op, [lhs, rhs]))
+    }
+
+    fn fold(view: &mut NodeView<'_, Expr, 2>) -> Outcome<Expr, 2> {
+        let (Some(Expr::Num(lhs)), Some(Expr::Num(rhs))) = (view.child(0), view.child(1)) else {
+            return Outcome::Unchanged;
+        };
+        let val = match view.value() {
+            Expr::Add => lhs + rhs,
+            Expr::Mul => lhs * rhs,
+            Expr::Num(_) => return Outcome::Unchanged,
+        };
+        Outcome::Replace(Tree::new(num(val)))
+    }
+
+    fn mul_by_one(view: &mut NodeView<'_, Expr, 2>) -> Outcome<Expr, 2> {
+        if *view.value() != Expr::Mul || view.child(1) != Some(&Expr::Num(1)) {
+            return Outcome::Unchanged;
+        }
+        Outcome::Replace(view.detach(0))
+    }
+
+    /// Rewrites `x + 0` to `x` by flipping the node into a `Mul` by one.
+    fn add_zero(view: &mut NodeView<'_, Expr, 2>) -> Outcome<Expr, 2> {
+        // Looking through `value_mut` alone isn't a change
+        if *view.value_mut() != Expr::Add || view.child(1) != Some(&Expr::Num(0)) {
+            return Outcome::Unchanged;
+        }
+        *view.value_mut() = Expr::Mul;
+        view.graft(1, Tree::new(num(1)));
+        Outcome::Changed
+    }
+
+    fn values(tree: &mut Tree<Expr, 2>) -> Vec<Expr> {
+        tree.dfs_iter_mut().map(|v| v.clone()).collect()
+    }
+
+    #[test]
+    fn constant_folds_to_root() {
+        let mut tree = Tree::new(op(
+            Expr::Add,
+            op(Expr::Mul, num(2), num(3)),
+            op(Expr::Add, num(4), num(5)),
+        ));
+        assert_eq!(3, rewrite(&mut tree, &[fold]));
+        assert_eq!(vec![Expr::Num(15)], values(&mut tree));
+    }
+
+    #[test]
+    fn parents_see_replaced_children() {
+        // (x * 1) * 1 where x is an unfoldable Add with a missing operand
+        let x = op(Expr::Add, num(7), None);
+        let mut tree = Tree::new(op(Expr::Mul, op(Expr::Mul, x, num(1)), num(1)));
+        assert_eq!(2, rewrite(&mut tree, &[fold, mul_by_one]));
+        assert_eq!(vec![Expr::Add, Expr::Num(7)], values(&mut tree));
+    }
+
+    #[test]
+    fn changed_in_place() {
+        let mut tree = Tree::new(op(Expr::Add, op(Expr::Add, num(3), None), num(0)));
+        assert_eq!(2, rewrite(&mut tree, &[add_zero, mul_by_one]));
+        assert_eq!(vec![Expr::Add, Expr::Num(3)], values(&mut tree));
+    }
+
+    #[test]
+    fn no_rules_fire() {
+        let mut tree = Tree::new(op(Expr::Add, num(1), None));
+        assert_eq!(0, rewrite(&mut tree, &[fold]));
+        assert_eq!(vec![Expr::Add, Expr::Num(1)], values(&mut tree));
+    }
+}
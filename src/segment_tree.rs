@@ -0,0 +1,311 @@
+use std::{ops::Range, ptr};
+
+use crate::{
+    binary_tree::{Node, NodeIter, Tree, LEFT, RIGHT},
+    tagged_ptr::TaggedPtr,
+};
+
+#[derive(Debug)]
+struct Segment<T> {
+    /// The covered range, `lo..hi`
+    lo: usize,
+    hi: usize,
+    agg: T,
+}
+
+impl<T> Segment<T> {
+    fn mid(&self) -> usize {
+        self.lo + (self.hi - self.lo) / 2
+    }
+}
+
+/// Range aggregation over a fixed-length sequence. `combine` must be
+/// associative but needn't be commutative.
+pub struct SegmentTree<T, F> {
+    tree: Tree<Segment<T>>,
+    len: usize,
+    combine: F,
+}
+
+fn leak<T>(val: T, left: *mut Node<T>, right: *mut Node<T>) -> *mut Node<T> {
+    Box::into_raw(Box::new(Node {
+        val,
+        children: [
+            TaggedPtr::from_untagged(left),
+            TaggedPtr::from_untagged(right),
+        ],
+    }))
+}
+
+/// Builds the shape for `data[range]`. Internal aggregates are left as
+/// placeholders for [`SegmentTree::recompute`] to fill in. The tree is
+/// balanced so this only recurses O(log n) deep.
+fn build<T: Clone>(data: &[T], range: Range<usize>) -> *mut Node<Segment<T>> {
+    let seg = Segment {
+        lo: range.start,
+        hi: range.end,
+        agg: data[range.start].clone(),
+    };
+    if range.len() == 1 {
+        return leak(seg, ptr::null_mut(), ptr::null_mut());
+    }
+    let mid = seg.mid();
+    let left = build(data, seg.lo..mid);
+    let right = build(data, mid..seg.hi);
+    leak(seg, left, right)
+}
+
+/// The path [`SegmentTree::update`] has reversed, from `cur` up through `prev`.
+/// Dropping it puts the rest of the links back, so a panic partway through
+/// leaves the tree whole.
+struct Restore<T> {
+    prev: *mut Node<Segment<T>>,
+    cur: *mut Node<Segment<T>>,
+}
+
+impl<T> Restore<T> {
+    /// Puts back the link from `prev` to `cur` and moves up a level.
+    fn step_up(&mut self) {
+        // SAFETY: `prev` is on the reversed path, with the seen slot holding
+        // its parent
+        let parent = unsafe { &mut *self.prev };
+        let slot = if parent.children[LEFT].is_seen() {
+            &mut parent.children[LEFT]
+        } else {
+            &mut parent.children[RIGHT]
+        };
+        let grandparent = std::mem::replace(slot, TaggedPtr::from_untagged(self.cur));
+        self.cur = self.prev;
+        self.prev = grandparent.as_untagged();
+    }
+}
+
+impl<T> Drop for Restore<T> {
+    fn drop(&mut self) {
+        while !self.prev.is_null() {
+            self.step_up();
+        }
+    }
+}
+
+impl<T: Clone, F: Fn(&T, &T) -> T> SegmentTree<T, F> {
+    pub fn new(data: &[T], combine: F) -> Self {
+        let root = if data.is_empty() {
+            ptr::null_mut()
+        } else {
+            build(data, 0..data.len())
+        };
+        let mut tree = Self {
+            tree: Tree::from_raw(root),
+            len: data.len(),
+            combine,
+        };
+        tree.recompute();
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Recomputes every internal aggregate from the leaves up.
+    pub fn recompute(&mut self) {
+        // Post-order, so both children are done by the time we see a node
+        for node in NodeIter::<_, 2>::new(self.tree.root) {
+            // SAFETY: The node is finished so its children are back in place
+            let node = unsafe { &mut *node };
+            let (Some(left), Some(right)) = (
+                unsafe { node.children[LEFT].as_untagged().as_ref() },
+                unsafe { node.children[RIGHT].as_untagged().as_ref() },
+            ) else {
+                continue;
+            };
+            node.val.agg = (self.combine)(&left.val.agg, &right.val.agg);
+        }
+    }
+
+    /// Replaces the element at `i`, fixing up the aggregates above it.
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn update(&mut self, i: usize, value: T) {
+        assert!(
+            i < self.len,
+            "index {i} out of bounds for length {}",
+            self.len
+        );
+        // Reverse the links on the way down so we can find our way back up.
+        // Until they're all put back, `restore` owns the reversed path.
+        let mut restore = Restore {
+            prev: ptr::null_mut(),
+            cur: self.tree.root.cast_mut(),
+        };
+        // SAFETY: The tree isn't being traversed since we hold `&mut self`
+        let mut node = unsafe { &mut *restore.cur };
+        while node.val.hi - node.val.lo > 1 {
+            let slot = if i < node.val.mid() {
+                &mut node.children[LEFT]
+            } else {
+                &mut node.children[RIGHT]
+            };
+            let next = slot.as_untagged();
+            *slot = TaggedPtr::from_untagged(restore.prev).seen();
+            restore.prev = restore.cur;
+            restore.cur = next;
+            node = unsafe { &mut *restore.cur };
+        }
+        node.val.agg = value;
+
+        while let Some(parent) = unsafe { restore.prev.as_mut() } {
+            // Combine before touching any links, so a panicking `combine`
+            // leaves the path as `restore` expects to find it
+            let from_left = parent.children[LEFT].is_seen();
+            let (left, right) = unsafe {
+                if from_left {
+                    (&*restore.cur, &*parent.children[RIGHT].as_untagged())
+                } else {
+                    (&*parent.children[LEFT].as_untagged(), &*restore.cur)
+                }
+            };
+            let agg = (self.combine)(&left.val.agg, &right.val.agg);
+            restore.step_up();
+            parent.val.agg = agg;
+        }
+    }
+
+    /// Combines the elements in `range`, or returns `None` if it's empty.
+    ///
+    /// Panics if `range` is out of bounds.
+    pub fn query(&self, range: Range<usize>) -> Option<T> {
+        let Range { start: l, end: r } = range;
+        assert!(
+            r <= self.len,
+            "range end {r} out of bounds for length {}",
+            self.len
+        );
+        if l >= r {
+            return None;
+        }
+        let seg = |node: *const Node<Segment<T>>| unsafe { &*node };
+
+        // Find where the ends of the range part ways
+        let mut split = seg(self.tree.root);
+        loop {
+            if l == split.val.lo && r == split.val.hi {
+                return Some(split.val.agg.clone());
+            }
+            let mid = split.val.mid();
+            if r <= mid {
+                split = seg(split.children[LEFT].as_untagged());
+            } else if l >= mid {
+                split = seg(split.children[RIGHT].as_untagged());
+            } else {
+                break;
+            }
+        }
+
+        // Down the left flank everything we take is left of what we've got
+        let mut left_acc: Option<T> = None;
+        let prepend = |acc: &mut Option<T>, val: &T| {
+            *acc = Some(match acc {
+                Some(acc) => (self.combine)(val, acc),
+                None => val.clone(),
+            })
+        };
+        let mut node = seg(split.children[LEFT].as_untagged());
+        while l != node.val.lo {
+            if l < node.val.mid() {
+                prepend(
+                    &mut left_acc,
+                    &seg(node.children[RIGHT].as_untagged()).val.agg,
+                );
+                node = seg(node.children[LEFT].as_untagged());
+            } else {
+                node = seg(node.children[RIGHT].as_untagged());
+            }
+        }
+        prepend(&mut left_acc, &node.val.agg);
+
+        // And down the right flank it's right of what we've got
+        let mut acc = left_acc;
+        let append = |acc: &mut Option<T>, val: &T| {
+            *acc = Some(match acc {
+                Some(acc) => (self.combine)(acc, val),
+                None => val.clone(),
+            })
+        };
+        let mut node = seg(split.children[RIGHT].as_untagged());
+        while r != node.val.hi {
+            if r > node.val.mid() {
+                append(&mut acc, &seg(node.children[LEFT].as_untagged()).val.agg);
+                node = seg(node.children[RIGHT].as_untagged());
+            } else {
+                node = seg(node.children[LEFT].as_untagged());
+            }
+        }
+        append(&mut acc, &node.val.agg);
+        acc
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn naive(data: &[String], range: Range<usize>) -> Option<String> {
+        (!range.is_empty()).then(|| data[range].concat())
+    }
+
+    #[test]
+    fn matches_naive() {
+        // Concatenation isn't commutative so this checks ordering too
+        let mut data: Vec<String> = (0..11).map(|i| i.to_string()).collect();
+        let mut tree = SegmentTree::new(&data, |a: &String, b: &String| format!("{a}{b}"));
+        for (i, value) in [(3, "x"), (0, "y"), (10, "z"), (5, "w")] {
+            for l in 0..=data.len() {
+                for r in l..=data.len() {
+                    assert_eq!(naive(&data, l..r), tree.query(l..r), "{l}..{r}");
+                }
+            }
+            data[i] = value.to_owned();
+            tree.update(i, value.to_owned());
+        }
+    }
+
+    #[test]
+    fn sums() {
+        let mut tree = SegmentTree::new(&[1, 2, 3, 4, 5], |a, b| a + b);
+        assert_eq!(Some(15), tree.query(0..5));
+        assert_eq!(Some(9), tree.query(1..4));
+        tree.update(2, 10);
+        assert_eq!(Some(16), tree.query(1..4));
+        assert_eq!(Some(10), tree.query(2..3));
+        assert_eq!(None, tree.query(3..3));
+    }
+
+    #[test]
+    fn combine_panics() {
+        let data: Vec<u32> = (0..11).collect();
+        let mut seg = SegmentTree::new(&data, |a: &u32, b: &u32| {
+            assert!(*a < 100 && *b < 100, "too big");
+            a + b
+        });
+        let panicked =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| seg.update(5, 1000)));
+        assert!(panicked.is_err());
+        assert_eq!(Some(1000), seg.query(5..6));
+        assert_eq!(Some(4), seg.query(4..5));
+        seg.update(5, 5);
+        assert_eq!(Some(55), seg.query(0..11));
+    }
+
+    #[test]
+    fn empty() {
+        let tree = SegmentTree::new(&[], |a: &i32, b: &i32| a + b);
+        assert!(tree.is_empty());
+        assert_eq!(None, tree.query(0..0));
+    }
+}
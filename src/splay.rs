@@ -0,0 +1,252 @@
+use std::{cmp::Ordering, mem, ptr};
+
+use crate::{
+    binary_tree::{take_for_rearranging, Node, Tree, LEFT, RIGHT},
+    tagged_ptr::TaggedPtr,
+};
+
+#[derive(Debug)]
+struct Item<K, V> {
+    key: K,
+    value: V,
+}
+
+type Link<K, V> = TaggedPtr<Node<Item<K, V>>>;
+
+/// An ordered map that moves every key it looks up to the root, so keys
+/// that were used recently are quick to get at again.
+///
+/// Splaying is top down, taking apart the path to the key into a left and a
+/// right tree as it goes, so it needs no stack or parent links.
+pub struct SplayTree<K, V> {
+    tree: Tree<Item<K, V>>,
+    len: usize,
+}
+
+fn link<K, V>(node: *mut Node<Item<K, V>>) -> Link<K, V> {
+    TaggedPtr::from_untagged(node)
+}
+
+/// Brings the node for `key` to the top of the subtree at `root`, or the last
+/// node on the way to where it would be if it isn't in there. Returns the new
+/// top.
+///
+/// # Safety
+///
+/// `root` must be null or a live subtree that nothing else is linked to.
+unsafe fn splay<K: Ord, V>(root: *mut Node<Item<K, V>>, key: &K) -> *mut Node<Item<K, V>> {
+    let Some(mut top) = (unsafe { root.as_mut() }) else {
+        return root;
+    };
+    let null = link(ptr::null_mut());
+    // Nodes less than the key, hung off one another's right slots, and nodes
+    // greater than it, hung off their left slots
+    let (mut less, mut greater) = (null, null);
+    let mut less_slot: *mut Link<K, V> = &mut less;
+    let mut greater_slot: *mut Link<K, V> = &mut greater;
+    loop {
+        let (near, far) = match key.cmp(&top.val.key) {
+            Ordering::Less => (LEFT, RIGHT),
+            Ordering::Greater => (RIGHT, LEFT),
+            Ordering::Equal => break,
+        };
+        let Some(mut child) = (unsafe { top.children[near].as_untagged().as_mut() }) else {
+            break;
+        };
+        // Going the same way twice, so rotate to keep the path short
+        if key.cmp(&child.val.key) == key.cmp(&top.val.key) {
+            top.children[near] = child.children[far];
+            child.children[far] = link(top);
+            top = child;
+            match unsafe { top.children[near].as_untagged().as_mut() } {
+                Some(next) => child = next,
+                None => break,
+            }
+        }
+        // Everything from `top` on the far side is past the key
+        let slot = if near == LEFT {
+            &mut greater_slot
+        } else {
+            &mut less_slot
+        };
+        unsafe { **slot = link(top) };
+        *slot = &mut top.children[near];
+        top = child;
+    }
+    unsafe {
+        *less_slot = top.children[LEFT];
+        *greater_slot = top.children[RIGHT];
+    }
+    top.children = [less, greater];
+    top
+}
+
+impl<K: Ord, V> SplayTree<K, V> {
+    pub fn new() -> Self {
+        Self {
+            tree: Tree::empty(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Splays `key` to the root and returns the root if it's the key's node.
+    fn splay(&mut self, key: &K) -> Option<&mut Node<Item<K, V>>> {
+        let (root, len) = take_for_rearranging(&mut self.tree, &mut self.len);
+        // SAFETY: We own the whole tree and nothing else is traversing it
+        self.tree = Tree::from_raw(unsafe { splay(root, key) });
+        self.len = len;
+        let root = unsafe { self.tree.root.cast_mut().as_mut()? };
+        (root.val.key == *key).then_some(root)
+    }
+
+    /// Looks up `key`, moving it to the root.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.splay(key).map(|node| &node.val.value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.splay(key).map(|node| &mut node.val.value)
+    }
+
+    /// Adds `key` at the root, returning its old value if it was there.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(node) = self.splay(&key) {
+            return Some(mem::replace(&mut node.val.value, value));
+        }
+        let (root, len) = take_for_rearranging(&mut self.tree, &mut self.len);
+        let node = Box::into_raw(Node::alloc(Item { key, value }, [None, None]));
+        // SAFETY: `root` is the closest node to the key, so its children on
+        // the key's side of it are all on that side of the key too
+        unsafe {
+            let new = &mut *node;
+            if let Some(old) = root.as_mut() {
+                let side = if new.val.key < old.val.key {
+                    LEFT
+                } else {
+                    RIGHT
+                };
+                new.children[side] = old.children[side];
+                new.children[1 - side] = link(root);
+                old.children[side] = link(ptr::null_mut());
+            }
+        }
+        self.tree = Tree::from_raw(node);
+        self.len = len + 1;
+        None
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.splay(key)?;
+        let (root, len) = take_for_rearranging(&mut self.tree, &mut self.len);
+        // SAFETY: The key's node is the root, so it's all ours once taken out
+        let node = unsafe { Box::from_raw(root) };
+        let [less, greater] = node.children.map(TaggedPtr::as_untagged);
+        // Everything left of the key is less than it, so splaying it there
+        // brings up a node with no right child
+        let joined = match unsafe { splay(less, key).as_mut() } {
+            Some(top) => {
+                top.children[RIGHT] = link(greater);
+                top
+            }
+            None => greater,
+        };
+        self.tree = Tree::from_raw(joined);
+        self.len = len - 1;
+        Some(node.val.value)
+    }
+
+    /// Iterates over the entries in order of their keys, without splaying.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.tree
+            .inorder_iter_mut()
+            .map(|item| (&item.key, &mut item.value))
+    }
+}
+
+impl<K: Ord, V> Default for SplayTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::leak_check::{set_fuse, Touchy};
+
+    fn root_key(tree: &SplayTree<u32, u32>) -> Option<u32> {
+        unsafe { tree.tree.root.as_ref() }.map(|node| node.val.key)
+    }
+
+    #[test]
+    fn matches_btree_map() {
+        let mut tree = SplayTree::new();
+        let mut model = BTreeMap::new();
+        let mut x = 1u32;
+        for _ in 0..2000 {
+            // A small LCG so keys repeat now and then
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let key = (x >> 16) % 200;
+            match x % 3 {
+                0 => assert_eq!(model.insert(key, x), tree.insert(key, x)),
+                1 => assert_eq!(model.remove(&key), tree.remove(&key)),
+                _ => assert_eq!(model.get(&key), tree.get(&key)),
+            }
+            assert_eq!(model.len(), tree.len());
+        }
+        let entries: Vec<_> = tree.iter_mut().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(model.into_iter().collect::<Vec<_>>(), entries);
+    }
+
+    #[test]
+    fn splays_to_root() {
+        let mut tree = SplayTree::new();
+        for k in 0..100 {
+            tree.insert(k, k);
+            assert_eq!(Some(k), root_key(&tree));
+        }
+        assert_eq!(Some(&37), tree.get(&37));
+        assert_eq!(Some(37), root_key(&tree));
+        *tree.get_mut(&5).unwrap() += 1;
+        assert_eq!(Some(5), root_key(&tree));
+        assert_eq!(None, tree.get(&1000));
+        assert_eq!(Some(6), tree.remove(&5));
+        assert_eq!(99, tree.len());
+    }
+
+    #[test]
+    fn cmp_panics() {
+        for fuse in 0..60 {
+            for op in 0..3 {
+                let mut tree = SplayTree::new();
+                for k in 0..16 {
+                    tree.insert(Touchy(k * 2), k);
+                }
+                set_fuse(fuse);
+                let key = Touchy(fuse as u32 % 33);
+                let panicked =
+                    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| match op {
+                        0 => drop(tree.insert(key, 0)),
+                        1 => drop(tree.remove(&key)),
+                        _ => drop(tree.get(&key)),
+                    }));
+                set_fuse(usize::MAX);
+                if panicked.is_err() {
+                    assert_eq!(tree.iter_mut().count(), tree.len());
+                    tree.insert(Touchy(7), 7);
+                    assert_eq!(tree.iter_mut().count(), tree.len());
+                }
+            }
+        }
+    }
+}
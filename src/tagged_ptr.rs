@@ -1,4 +1,4 @@
-use std::fmt::{self, Debug, Write as _};
+use std::fmt::{self, Debug};
 
 /// This cannot be used on types with alignment == 1.
 ///
@@ -13,19 +13,23 @@ use std::fmt::{self, Debug, Write as _};
 /// let mut v: u8 = 1;
 /// let ptr = TaggedPtr::from_untagged(&mut v);
 /// ```
-pub struct TaggedPtr<T>(*mut T);
+///
+/// The lowest bit marks whether a traversal has passed through the link. The
+/// other bits that alignment leaves free are spare and can be claimed with
+/// [`TaggedPtr::with_tag`]. A tag belongs to the child slot it was set on:
+/// traversals leave it there, and so does anything that puts a different child
+/// in the slot.
+pub struct TaggedPtr<T>(*const T);
 const SEEN_BIT: usize = 1;
 
-impl<T: Debug> Debug for TaggedPtr<T> {
+/// Only shows the address, the seen bit and the tag, as `<0x..|seen|tag>`.
+/// Following the pointer could recurse as deep as a tree goes, so trees print
+/// their own nodes instead.
+impl<T> Debug for TaggedPtr<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let ptr = self.as_untagged();
         let flag = self.0 as usize & SEEN_BIT;
-        write!(f, "<0x{:0x}|{}>", ptr as usize, flag)?;
-        if let Some(node) = unsafe { ptr.as_ref() } {
-            f.write_char(' ')?;
-            node.fmt(f)?;
-        }
-        Ok(())
+        write!(f, "<0x{:0x}|{}|{}>", ptr as usize, flag, self.tag())
     }
 }
 
@@ -39,6 +43,10 @@ impl<T> Copy for TaggedPtr<T> {}
 
 impl<T> TaggedPtr<T> {
     const ALIGN_OK: () = assert!(align_of::<T>() > 1);
+    const LOW_BITS: usize = align_of::<T>() - 1;
+
+    /// How many bits beside the seen bit are free to tag with.
+    pub const AVAILABLE_BITS: u32 = align_of::<T>().trailing_zeros() - 1;
 
     pub const fn from_untagged(ptr: *mut T) -> Self {
         let () = Self::ALIGN_OK;
@@ -46,10 +54,43 @@ impl<T> TaggedPtr<T> {
     }
 
     pub fn as_untagged(self) -> *mut T {
-        let addr = self.0 as usize & !SEEN_BIT;
+        let addr = self.0 as usize & !Self::LOW_BITS;
         addr as _
     }
 
+    /// The spare bits, shifted down to start from bit 0.
+    pub fn tag(self) -> usize {
+        (self.0 as usize & Self::LOW_BITS) >> 1
+    }
+
+    /// Sets the spare bits to `tag`, keeping the pointer and seen bit.
+    ///
+    /// Panics if `tag` doesn't fit in [`Self::AVAILABLE_BITS`].
+    ///
+    /// ```rust
+    /// use constant_size_dfs::tagged_ptr::TaggedPtr;
+    /// let mut v: u32 = 1;
+    /// let ptr = TaggedPtr::from_untagged(&mut v).with_tag(1);
+    /// assert_eq!(1, TaggedPtr::<u32>::AVAILABLE_BITS);
+    /// assert_eq!(1, ptr.tag());
+    /// assert_eq!(&mut v as *mut u32, ptr.as_untagged());
+    /// ```
+    pub fn with_tag(self, tag: usize) -> Self {
+        assert!(
+            tag >> Self::AVAILABLE_BITS == 0,
+            "tag {tag:#x} doesn't fit in {} bits",
+            Self::AVAILABLE_BITS
+        );
+        let addr = (self.0 as usize & !(Self::LOW_BITS & !SEEN_BIT)) | tag << 1;
+        Self(addr as _)
+    }
+
+    /// Points the link at `ptr` instead, keeping the tag but not the seen bit.
+    pub(crate) fn retarget(self, ptr: *mut T) -> Self {
+        let tag = self.0 as usize & Self::LOW_BITS & !SEEN_BIT;
+        Self((ptr as usize | tag) as _)
+    }
+
     pub fn is_seen(self) -> bool {
         self.0 as usize & SEEN_BIT == SEEN_BIT
     }
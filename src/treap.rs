@@ -0,0 +1,544 @@
+use std::{cmp::Ordering, mem, ptr};
+
+use crate::{
+    binary_tree::{take_for_rearranging, Node, Tree, LEFT, RIGHT},
+    tagged_ptr::TaggedPtr,
+};
+
+#[derive(Debug)]
+struct Item<K, V> {
+    key: K,
+    value: V,
+    priority: u32,
+    /// How many nodes there are in the subtree, counting this one.
+    size: usize,
+}
+
+type Link<K, V> = TaggedPtr<Node<Item<K, V>>>;
+
+/// How many nodes there are in the subtree at `link`.
+///
+/// # Safety
+///
+/// `link` must be a live subtree.
+unsafe fn size<K, V>(link: Link<K, V>) -> usize {
+    unsafe { link.as_untagged().as_ref() }.map_or(0, |node| node.val.size)
+}
+
+/// An ordered map kept balanced in expectation by giving every key a random
+/// priority and keeping the tree a heap on those.
+///
+/// Inserting and removing split and merge subtrees top down, so neither
+/// needs rotations or a stack. Every node keeps the size of its subtree, so
+/// entries can be looked up by position with [`Treap::select`] and
+/// [`Treap::rank`].
+pub struct Treap<K, V> {
+    tree: Tree<Item<K, V>>,
+    len: usize,
+    rng: u64,
+}
+
+/// Splits the subtree at `link` into the keys before `key` and the keys after
+/// it. `key` itself mustn't be in there.
+///
+/// # Safety
+///
+/// `link` must be a live subtree that nothing else is linked to.
+unsafe fn split<K: Ord, V>(mut link: Link<K, V>, key: &K) -> (Link<K, V>, Link<K, V>) {
+    let null = TaggedPtr::from_untagged(ptr::null_mut());
+    let (mut before, mut after) = (null, null);
+    // Where the next node of each half goes
+    let mut before_slot: *mut Link<K, V> = &mut before;
+    let mut after_slot: *mut Link<K, V> = &mut after;
+    // Sizes are fixed on the way down, which needs to know how many keys of
+    // each subtree end up before `key`
+    let mut less = 0;
+    let mut node = link.as_untagged();
+    while let Some(cur) = unsafe { node.as_ref() } {
+        if cur.val.key < *key {
+            less += 1 + unsafe { size(cur.children[LEFT]) };
+            node = cur.children[RIGHT].as_untagged();
+        } else {
+            node = cur.children[LEFT].as_untagged();
+        }
+    }
+    while let Some(node) = unsafe { link.as_untagged().as_mut() } {
+        if node.val.key < *key {
+            node.val.size = less;
+            less -= 1 + unsafe { size(node.children[LEFT]) };
+            unsafe { *before_slot = link };
+            before_slot = &mut node.children[RIGHT];
+            link = node.children[RIGHT];
+        } else {
+            node.val.size -= less;
+            unsafe { *after_slot = link };
+            after_slot = &mut node.children[LEFT];
+            link = node.children[LEFT];
+        }
+    }
+    unsafe { (*before_slot, *after_slot) = (null, null) };
+    (before, after)
+}
+
+/// Joins two subtrees where every key in `before` is less than every key in
+/// `after`.
+///
+/// # Safety
+///
+/// Both must be live subtrees that nothing else is linked to.
+unsafe fn merge<K, V>(mut before: Link<K, V>, mut after: Link<K, V>) -> Link<K, V> {
+    let mut merged = TaggedPtr::from_untagged(ptr::null_mut());
+    let mut slot: *mut Link<K, V> = &mut merged;
+    loop {
+        let (b, a) = unsafe { (before.as_untagged().as_mut(), after.as_untagged().as_mut()) };
+        match (b, a) {
+            (None, _) => break unsafe { *slot = after },
+            (_, None) => break unsafe { *slot = before },
+            (Some(b), Some(a)) if b.val.priority > a.val.priority => {
+                b.val.size += a.val.size;
+                unsafe { *slot = before };
+                slot = &mut b.children[RIGHT];
+                before = b.children[RIGHT];
+            }
+            (b, Some(a)) => {
+                a.val.size += b.map_or(0, |b| b.val.size);
+                unsafe { *slot = after };
+                slot = &mut a.children[LEFT];
+                after = a.children[LEFT];
+            }
+        }
+    }
+    merged
+}
+
+/// The leftmost node of the subtree at `link`, or null if it's empty.
+///
+/// # Safety
+///
+/// `link` must be a live subtree.
+unsafe fn leftmost<K, V>(link: Link<K, V>) -> *mut Node<Item<K, V>> {
+    let mut node = link.as_untagged();
+    while let Some(left) = unsafe { node.as_ref() }.map(|n| n.children[LEFT].as_untagged()) {
+        if left.is_null() {
+            break;
+        }
+        node = left;
+    }
+    node
+}
+
+/// Adds `node` to a treap being built from increasing keys, where `bottom` is
+/// the bottom of its right spine. Returns the new bottom.
+///
+/// The spine's right links point back up instead of down, so nodes can be
+/// taken off the bottom without a stack. [`finish_run`] turns them back around.
+///
+/// # Safety
+///
+/// `bottom` must have come from this function or be null, and `node` must be
+/// a lone node with a greater key than any before it.
+unsafe fn push_run<K, V>(
+    mut bottom: *mut Node<Item<K, V>>,
+    node: *mut Node<Item<K, V>>,
+) -> *mut Node<Item<K, V>> {
+    let priority = unsafe { (*node).val.priority };
+    // Everything on the spine that `node` outranks becomes its left subtree
+    let mut below = ptr::null_mut();
+    while let Some(spine) = unsafe { bottom.as_mut() } {
+        if spine.val.priority >= priority {
+            break;
+        }
+        let up = spine.children[RIGHT].as_untagged();
+        spine.children[RIGHT] = TaggedPtr::from_untagged(below);
+        spine.val.size += unsafe { size(spine.children[RIGHT]) };
+        below = spine;
+        bottom = up;
+    }
+    unsafe { (*node).val.size += size(TaggedPtr::from_untagged(below)) };
+    unsafe {
+        (*node).children = [
+            TaggedPtr::from_untagged(below),
+            TaggedPtr::from_untagged(bottom),
+        ]
+    };
+    node
+}
+
+/// Points the right spine built by [`push_run`] back down, returning the root.
+///
+/// # Safety
+///
+/// `bottom` must have come from [`push_run`].
+unsafe fn finish_run<K, V>(mut bottom: *mut Node<Item<K, V>>) -> Link<K, V> {
+    let mut below = ptr::null_mut();
+    while let Some(spine) = unsafe { bottom.as_mut() } {
+        let up = spine.children[RIGHT].as_untagged();
+        spine.children[RIGHT] = TaggedPtr::from_untagged(below);
+        spine.val.size += unsafe { size(spine.children[RIGHT]) };
+        below = spine;
+        bottom = up;
+    }
+    TaggedPtr::from_untagged(below)
+}
+
+impl<K: Ord, V> Treap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            tree: Tree::empty(),
+            len: 0,
+            rng: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// SplitMix64, which is plenty random for balancing
+    fn next_priority(&mut self) -> u32 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        ((z ^ (z >> 31)) >> 32) as u32
+    }
+
+    /// A new node, not linked to anything yet.
+    fn alloc(&mut self, key: K, value: V) -> *mut Node<Item<K, V>> {
+        let priority = self.next_priority();
+        Box::into_raw(Node::alloc(
+            Item {
+                key,
+                value,
+                priority,
+                size: 1,
+            },
+            [None, None],
+        ))
+    }
+
+    fn find(&self, key: &K) -> *mut Node<Item<K, V>> {
+        let mut node = self.tree.root.cast_mut();
+        // SAFETY: Nothing is traversing the tree outside of our own methods
+        while let Some(cur) = unsafe { node.as_ref() } {
+            node = match key.cmp(&cur.val.key) {
+                Ordering::Less => cur.children[LEFT].as_untagged(),
+                Ordering::Greater => cur.children[RIGHT].as_untagged(),
+                Ordering::Equal => break,
+            };
+        }
+        node
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        unsafe { self.find(key).as_ref() }.map(|node| &node.val.value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        unsafe { self.find(key).as_mut() }.map(|node| &mut node.val.value)
+    }
+
+    /// Returns the old value for `key`, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(mem::replace(old, value));
+        }
+        let node = self.alloc(key, value);
+        // SAFETY: Nothing else has seen the node yet
+        let (key, priority) = unsafe { (&(*node).val.key, (*node).val.priority) };
+        let (root, len) = take_for_rearranging(&mut self.tree, &mut self.len);
+        let mut root = TaggedPtr::from_untagged(root);
+        // Go down to the first node the new one outranks
+        let mut slot: *mut Link<K, V> = &mut root;
+        // SAFETY: We own every node we're passing through
+        while let Some(node) = unsafe { (*slot).as_untagged().as_mut() } {
+            if node.val.priority < priority {
+                break;
+            }
+            node.val.size += 1;
+            let i = if *key < node.val.key { LEFT } else { RIGHT };
+            slot = &mut node.children[i];
+        }
+        let (before, after) = unsafe { split(*slot, key) };
+        unsafe {
+            (*node).val.size += size(before) + size(after);
+            (*node).children = [before, after];
+            *slot = TaggedPtr::from_untagged(node);
+        }
+        self.tree = Tree::from_raw(root.as_untagged());
+        self.len = len + 1;
+        None
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        // Sizes are fixed on the way down, so make sure it's there first
+        if self.find(key).is_null() {
+            return None;
+        }
+        let (root, len) = take_for_rearranging(&mut self.tree, &mut self.len);
+        let mut root = TaggedPtr::from_untagged(root);
+        let mut slot: *mut Link<K, V> = &mut root;
+        let mut removed = None;
+        while let Some(node) = unsafe { (*slot).as_untagged().as_mut() } {
+            node.val.size -= 1;
+            match key.cmp(&node.val.key) {
+                Ordering::Less => slot = &mut node.children[LEFT],
+                Ordering::Greater => slot = &mut node.children[RIGHT],
+                Ordering::Equal => {
+                    let [before, after] = node.children;
+                    // SAFETY: The node's been unlinked so nothing else can
+                    // reach its children
+                    unsafe {
+                        *slot = merge(before, after);
+                        removed = Some(Box::from_raw(node).val.value);
+                    }
+                    break;
+                }
+            }
+        }
+        self.tree = Tree::from_raw(root.as_untagged());
+        self.len = len - removed.is_some() as usize;
+        removed
+    }
+
+    /// The entry with `k` keys before it, if there are that many.
+    pub fn select(&self, mut k: usize) -> Option<(&K, &V)> {
+        let mut node = self.tree.root;
+        // SAFETY: Nothing is traversing the tree outside of our own methods
+        while let Some(cur) = unsafe { node.as_ref() } {
+            let left = unsafe { size(cur.children[LEFT]) };
+            node = match k.cmp(&left) {
+                Ordering::Less => cur.children[LEFT].as_untagged(),
+                Ordering::Equal => return Some((&cur.val.key, &cur.val.value)),
+                Ordering::Greater => {
+                    k -= left + 1;
+                    cur.children[RIGHT].as_untagged()
+                }
+            };
+        }
+        None
+    }
+
+    /// How many keys are less than `key`, whether or not it's in the treap.
+    pub fn rank(&self, key: &K) -> usize {
+        let mut rank = 0;
+        let mut node = self.tree.root;
+        // SAFETY: Nothing is traversing the tree outside of our own methods
+        while let Some(cur) = unsafe { node.as_ref() } {
+            node = match key.cmp(&cur.val.key) {
+                Ordering::Less => cur.children[LEFT].as_untagged(),
+                Ordering::Equal => return rank + unsafe { size(cur.children[LEFT]) },
+                Ordering::Greater => {
+                    rank += 1 + unsafe { size(cur.children[LEFT]) };
+                    cur.children[RIGHT].as_untagged()
+                }
+            };
+        }
+        rank
+    }
+
+    /// Iterates over the entries in order of their keys.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.tree
+            .inorder_iter_mut()
+            .map(|item| (&item.key, &mut item.value))
+    }
+}
+
+/// Each run of increasing keys that fits between two keys already in the treap
+/// is built on its own in linear time and merged in whole, so loading sorted
+/// entries walks the tree once per run rather than once per entry.
+impl<K: Ord, V> Extend<(K, V)> for Treap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let mut iter = iter.into_iter().peekable();
+        while let Some((key, value)) = iter.next() {
+            if let Some(old) = self.get_mut(&key) {
+                *old = value;
+                continue;
+            }
+            let (root, len) = take_for_rearranging(&mut self.tree, &mut self.len);
+            let root = TaggedPtr::from_untagged(root);
+            // SAFETY: We own the whole tree, and `key` isn't in it
+            let (before, after) = unsafe { split(root, &key) };
+            // The run has to stay below everything in `after`
+            let bound = unsafe { leftmost(after).as_ref() }.map(|node| &node.val.key);
+            let mut bottom = unsafe { push_run(ptr::null_mut(), self.alloc(key, value)) };
+            let mut added = 1;
+            while let Some((key, value)) = iter.next_if(|(key, _)| {
+                // SAFETY: The bottom of the spine is the last node pushed
+                let last = unsafe { &(*bottom).val.key };
+                key > last && bound.is_none_or(|bound| key < bound)
+            }) {
+                bottom = unsafe { push_run(bottom, self.alloc(key, value)) };
+                added += 1;
+            }
+            // SAFETY: Every key in the run is between those of `before` and
+            // `after`
+            let merged = unsafe { merge(merge(before, finish_run(bottom)), after) };
+            self.tree = Tree::from_raw(merged.as_untagged());
+            self.len = len + added;
+        }
+    }
+}
+
+impl<K: Ord, V> Default for Treap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        binary_tree::NodeIter,
+        leak_check::{set_fuse, Touchy},
+        observer::MaxDepth,
+    };
+
+    fn keys(treap: &mut Treap<u32, u32>) -> Vec<u32> {
+        treap.iter_mut().map(|(k, _)| *k).collect()
+    }
+
+    #[test]
+    fn map() {
+        let mut treap = Treap::new();
+        for k in [5, 2, 8, 1, 9, 3] {
+            assert_eq!(None, treap.insert(k, k * 10));
+        }
+        assert_eq!(Some(20), treap.insert(2, 21));
+        assert_eq!(6, treap.len());
+        assert_eq!(Some(&21), treap.get(&2));
+        assert_eq!(None, treap.get(&4));
+        *treap.get_mut(&9).unwrap() += 1;
+        assert_eq!(vec![1, 2, 3, 5, 8, 9], keys(&mut treap));
+
+        assert_eq!(Some(50), treap.remove(&5));
+        assert_eq!(None, treap.remove(&5));
+        assert_eq!(Some(91), treap.remove(&9));
+        assert_eq!(vec![1, 2, 3, 8], keys(&mut treap));
+        assert_eq!(4, treap.len());
+        assert_heap(&treap);
+    }
+
+    #[test]
+    fn select_and_rank() {
+        let mut treap = Treap::new();
+        treap.extend((0..200).map(|k| (k * 3, k)));
+        for k in (0..600).step_by(7) {
+            treap.insert(k, k);
+        }
+        for k in (0..600).step_by(5) {
+            treap.remove(&k);
+        }
+        assert_heap(&treap);
+        let keys = keys(&mut treap);
+        assert_eq!(keys.len(), treap.len());
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(Some(k), treap.select(i).map(|(k, _)| k));
+            assert_eq!(i, treap.rank(k));
+            if !keys.contains(&(k + 1)) {
+                assert_eq!(i + 1, treap.rank(&(k + 1)));
+            }
+        }
+        assert_eq!(None, treap.select(keys.len()));
+        assert_eq!(keys.len(), treap.rank(&u32::MAX));
+    }
+
+    /// Checks that every node outranks its children and knows its size.
+    fn assert_heap(treap: &Treap<u32, u32>) {
+        // Post-order, so every node's children are back in place
+        for node in NodeIter::<_, 2>::new(treap.tree.root) {
+            let node = unsafe { &*node };
+            let mut size = 1;
+            for child in &node.children {
+                if let Some(child) = unsafe { child.as_untagged().as_ref() } {
+                    assert!(child.val.priority <= node.val.priority);
+                    size += child.val.size;
+                }
+            }
+            assert_eq!(size, node.val.size);
+        }
+    }
+
+    #[test]
+    fn extend() {
+        let mut treap = Treap::new();
+        treap.extend((0..1000).map(|k| (k * 2, k)));
+        assert_eq!(1000, treap.len());
+        assert_heap(&treap);
+        let mut depth = MaxDepth::default();
+        treap.tree.dfs_iter_mut_with(&mut depth).for_each(drop);
+        assert!(depth.max_depth_seen() < 40, "{}", depth.max_depth_seen());
+
+        // Runs that cut across existing keys, going backwards and repeating
+        treap.extend([
+            (1, 0),
+            (3, 0),
+            (5, 0),
+            (4, 7),
+            (2, 9),
+            (2, 8),
+            (2001, 0),
+            (9, 0),
+        ]);
+        assert_eq!(1005, treap.len());
+        assert_eq!(Some(&8), treap.get(&2));
+        assert_eq!(Some(&7), treap.get(&4));
+        assert_heap(&treap);
+        let mut expected: Vec<u32> = (0..1000).map(|k| k * 2).collect();
+        expected.extend([1, 3, 5, 9, 2001]);
+        expected.sort();
+        assert_eq!(expected, keys(&mut treap));
+    }
+
+    #[test]
+    fn balanced() {
+        let mut treap = Treap::new();
+        for k in 0..1000 {
+            treap.insert(k, k);
+        }
+        for k in (0..1000).step_by(3) {
+            treap.remove(&k);
+        }
+        assert_eq!(
+            (0..1000).filter(|k| k % 3 != 0).collect::<Vec<_>>(),
+            keys(&mut treap)
+        );
+
+        // Sorted input would make a plain search tree a list
+        let mut depth = MaxDepth::default();
+        treap.tree.dfs_iter_mut_with(&mut depth).for_each(drop);
+        assert!(depth.max_depth_seen() < 40, "{}", depth.max_depth_seen());
+        assert_heap(&treap);
+    }
+
+    #[test]
+    fn cmp_panics() {
+        for fuse in 0..60 {
+            for op in 0..3 {
+                let mut treap = Treap::new();
+                treap.extend((0..16).map(|k| (Touchy(k * 2), k)));
+                set_fuse(fuse);
+                let panicked =
+                    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| match op {
+                        0 => drop(treap.insert(Touchy(fuse as u32 % 33), 0)),
+                        1 => drop(treap.remove(&Touchy(fuse as u32 % 33))),
+                        _ => treap.extend((17..20).map(|k| (Touchy(k), k))),
+                    }));
+                set_fuse(usize::MAX);
+                if panicked.is_err() {
+                    // Whatever was being rearranged is leaked, and `len`
+                    // has to agree
+                    assert_eq!(treap.iter_mut().count(), treap.len());
+                    treap.insert(Touchy(7), 7);
+                    assert_eq!(treap.iter_mut().count(), treap.len());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::ptr;
+
+use crate::{
+    array_tree::{self, Node, NodeIter, Tree},
+    tagged_ptr::TaggedPtr,
+};
+
+const FANOUT: usize = 16;
+
+type TrieNode<V> = Node<Option<V>, FANOUT>;
+
+/// A map from byte strings to values, branching on one nibble per level.
+pub struct Trie<V> {
+    tree: Tree<Option<V>, FANOUT>,
+}
+
+fn nibbles(key: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    key.iter()
+        .flat_map(|b| [(b >> 4) as usize, (b & 0xf) as usize])
+}
+
+fn empty_node<V>() -> Box<TrieNode<V>> {
+    Node::alloc(None, [const { None }; FANOUT])
+}
+
+impl<V> Default for Trie<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Trie<V> {
+    pub fn new() -> Self {
+        Self {
+            tree: Tree::new(Some(empty_node())),
+        }
+    }
+
+    /// Finds the node for `key`, if there is one.
+    fn find(&self, key: &[u8]) -> *mut TrieNode<V> {
+        let mut node = self.tree.root.cast_mut();
+        for nibble in nibbles(key) {
+            // SAFETY: No traversal is in progress outside of our own methods
+            let Some(cur) = (unsafe { node.as_ref() }) else {
+                break;
+            };
+            node = cur.children[nibble].as_untagged();
+        }
+        node
+    }
+
+    /// Returns the old value for `key`, if any.
+    pub fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        let mut node = self.tree.root.cast_mut();
+        for nibble in nibbles(key) {
+            let cur = unsafe { &mut *node };
+            if cur.children[nibble].as_untagged().is_null() {
+                let child = Box::leak(empty_node());
+                cur.children[nibble] = TaggedPtr::from_untagged(child);
+            }
+            node = cur.children[nibble].as_untagged();
+        }
+        unsafe { (*node).val.replace(value) }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        unsafe { self.find(key).as_ref()?.val.as_ref() }
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        unsafe { self.find(key).as_mut()?.val.as_mut() }
+    }
+
+    /// Removes `key`, freeing any nodes that no longer lead to a value.
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        // The last slot on the key's path which is safe to cut off below
+        let mut cut: Option<(*mut TrieNode<V>, usize)> = None;
+        let mut node = self.tree.root.cast_mut();
+        for nibble in nibbles(key) {
+            let cur = unsafe { node.as_mut()? };
+            let branches = cur
+                .children
+                .iter()
+                .filter(|c| !c.as_untagged().is_null())
+                .count();
+            if cut.is_none() || cur.val.is_some() || branches > 1 {
+                cut = Some((cur, nibble));
+            }
+            node = cur.children[nibble].as_untagged();
+        }
+        let target = unsafe { node.as_mut()? };
+        let value = target.val.take()?;
+        let is_leaf = target.children.iter().all(|c| c.as_untagged().is_null());
+        if let (true, Some((parent, slot))) = (is_leaf, cut) {
+            let parent = unsafe { &mut *parent };
+            let detached = std::mem::replace(
+                &mut parent.children[slot],
+                TaggedPtr::from_untagged(ptr::null_mut()),
+            );
+            drop(Tree::<_, FANOUT>::from_raw(detached.as_untagged()));
+        }
+        Some(value)
+    }
+
+    /// Calls `f` with every key starting with `prefix` and its value, in
+    /// lexicographic order.
+    pub fn for_each_prefix(&mut self, prefix: &[u8], mut f: impl FnMut(&[u8], &mut V)) {
+        let start = self.find(prefix);
+        let mut key = Vec::new();
+        let mut iter = NodeIter::<_, FANOUT, 0>::new(start);
+        while let Some((node, visited)) = iter.step() {
+            let Some(val) = (visited == 0)
+                .then(|| unsafe { (*node).val.as_mut() })
+                .flatten()
+            else {
+                continue;
+            };
+            // SAFETY: We've only just stepped into this node. The walk ends at
+            // `start` since it's the root as far as the iterator knows.
+            let mut path: Vec<u8> = unsafe { array_tree::ancestors(node) }
+                .map(|(_, nibble)| nibble as u8)
+                .collect();
+            path.reverse();
+            key.clear();
+            key.extend_from_slice(prefix);
+            key.extend(path.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+            f(&key, val);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn insert_get() {
+        let mut trie = Trie::new();
+        assert_eq!(None, trie.insert(b"hello", 1));
+        assert_eq!(None, trie.insert(b"help", 2));
+        assert_eq!(None, trie.insert(b"", 3));
+        assert_eq!(Some(1), trie.insert(b"hello", 4));
+        assert_eq!(Some(&4), trie.get(b"hello"));
+        assert_eq!(Some(&2), trie.get(b"help"));
+        assert_eq!(Some(&3), trie.get(b""));
+        assert_eq!(None, trie.get(b"hel"));
+        assert_eq!(None, trie.get(b"helpful"));
+        *trie.get_mut(b"help").unwrap() += 10;
+        assert_eq!(Some(&12), trie.get(b"help"));
+    }
+
+    #[test]
+    fn remove_prunes() {
+        let mut trie = Trie::new();
+        trie.insert(b"ab", 1);
+        trie.insert(b"abcd", 2);
+        assert_eq!(None, trie.remove(b"abc"));
+        assert_eq!(Some(2), trie.remove(b"abcd"));
+        assert_eq!(None, trie.remove(b"abcd"));
+        assert_eq!(Some(&1), trie.get(b"ab"));
+        // Everything below "ab" should be gone, leaving the root plus 4 nibbles
+        assert_eq!(5, trie.tree.dfs_iter_mut().count());
+        assert_eq!(Some(1), trie.remove(b"ab"));
+        assert_eq!(1, trie.tree.dfs_iter_mut().count());
+    }
+
+    #[test]
+    fn prefix_iteration() {
+        let mut trie = Trie::new();
+        for (i, key) in ["car", "cart", "cat", "dog", "ca"].iter().enumerate() {
+            trie.insert(key.as_bytes(), i);
+        }
+        let mut found = Vec::new();
+        trie.for_each_prefix(b"car", |key, val| {
+            found.push((String::from_utf8(key.to_vec()).unwrap(), *val))
+        });
+        assert_eq!(vec![("car".to_owned(), 0), ("cart".to_owned(), 1)], found);
+
+        let mut found = Vec::new();
+        trie.for_each_prefix(b"", |key, _| found.push(key.to_vec()));
+        let expected: Vec<&[u8]> = vec![b"ca", b"car", b"cart", b"cat", b"dog"];
+        assert_eq!(expected, found);
+
+        trie.for_each_prefix(b"x", |_, _| panic!("nothing starts with x"));
+    }
+}